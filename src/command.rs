@@ -0,0 +1,244 @@
+use std::mem;
+
+use twilight_model::id::{marker::UserMarker, Id};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CommandKind { New, Join, Start, Leave, Kick, Cancel, Host, Set, Help }
+
+// What a command will accept after its name.
+#[derive(Clone, Copy)]
+enum Arguments {
+  Nothing,
+  OneMention,
+  Settings,
+  // `help` can be asked about a single command.
+  CommandName,
+}
+
+pub struct CommandSpec {
+  pub kind: CommandKind,
+  pub name: &'static str,
+  usage: &'static str,
+  description: &'static str,
+  arguments: Arguments,
+}
+
+impl CommandSpec {
+  pub fn usage(&self, prefix: &str) -> String {
+    format!("`{}{}`", prefix, self.usage)
+  }
+}
+
+const SETTINGS_HELP: &str = "Settings look like `rounds=5 time=30 difficulty=hard block=2 scoring=scrabble q=5 long=8 rare=on unique=off hidden=2 play=thread guesses=visible`.";
+
+pub const COMMANDS: [CommandSpec; 9] = [
+  CommandSpec { kind: CommandKind::New, name: "new", usage: "new [settings]", description: "Opens a lobby in this channel.", arguments: Arguments::Settings },
+  CommandSpec { kind: CommandKind::Join, name: "join", usage: "join", description: "Joins the lobby.", arguments: Arguments::Nothing },
+  CommandSpec { kind: CommandKind::Start, name: "start", usage: "start", description: "Starts the game. Host only.", arguments: Arguments::Nothing },
+  CommandSpec { kind: CommandKind::Leave, name: "leave", usage: "leave", description: "Leaves the lobby.", arguments: Arguments::Nothing },
+  CommandSpec { kind: CommandKind::Kick, name: "kick", usage: "kick @player", description: "Removes a player from the lobby. Host only.", arguments: Arguments::OneMention },
+  CommandSpec { kind: CommandKind::Cancel, name: "cancel", usage: "cancel", description: "Closes the lobby. Host or moderators only.", arguments: Arguments::Nothing },
+  CommandSpec { kind: CommandKind::Host, name: "host", usage: "host @player", description: "Hands the lobby to another player. Host only.", arguments: Arguments::OneMention },
+  CommandSpec { kind: CommandKind::Set, name: "set", usage: "set [settings]", description: "Shows the lobby's settings, or changes them if you're the host.", arguments: Arguments::Settings },
+  CommandSpec { kind: CommandKind::Help, name: "help", usage: "help [command]", description: "Lists commands, or explains one.", arguments: Arguments::CommandName },
+];
+
+/// A command as typed after the prefix, e.g. `set rounds=5 "scrabble"`.
+pub struct Command {
+  pub spec: &'static CommandSpec,
+  /// Bare words, in order.
+  pub args: Vec<String>,
+  /// `key=value` pairs, in order.
+  pub options: Vec<(String, String)>,
+  pub mentions: Vec<Id<UserMarker>>,
+}
+
+pub enum CommandError {
+  Empty,
+  UnclosedQuote,
+  Unknown(String),
+  Usage(&'static CommandSpec),
+}
+
+impl CommandError {
+  pub fn describe(&self, prefix: &str) -> String {
+    match self {
+      Self::Empty => format!("Try `{}help` for a list of commands.", prefix),
+      Self::UnclosedQuote => String::from("You left a quote open, close it with another `\"`."),
+      Self::Unknown(name) => format!("There's no `{}{}` command, try `{}help` for a list of commands.", prefix, name, prefix),
+      Self::Usage(spec) => format!("Usage: {} {}", spec.usage(prefix), spec.description),
+    }
+  }
+}
+
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+  COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// Parses everything after the prefix.
+pub fn parse(input: &str) -> Result<Command, CommandError> {
+  let mut tokens = tokenize(input)?.into_iter();
+  let name = tokens.next().ok_or(CommandError::Empty)?.to_lowercase();
+  let spec = find(&name).ok_or(CommandError::Unknown(name))?;
+
+  let mut command = Command { spec, args: vec![], options: vec![], mentions: vec![] };
+  for token in tokens {
+    if let Some(mention) = parse_mention(&token) {
+      command.mentions.push(mention);
+    } else if let Some((key, value)) = token.split_once('=') {
+      command.options.push((key.to_lowercase(), value.to_string()));
+    } else {
+      command.args.push(token);
+    }
+  }
+
+  let fits = match spec.arguments {
+    Arguments::Nothing => command.args.is_empty() && command.options.is_empty() && command.mentions.is_empty(),
+    Arguments::OneMention => command.args.is_empty() && command.options.is_empty() && command.mentions.len() == 1,
+    Arguments::Settings => command.mentions.is_empty(),
+    Arguments::CommandName => command.args.len() <= 1 && command.options.is_empty() && command.mentions.is_empty(),
+  };
+  if !fits {
+    return Err(CommandError::Usage(spec));
+  }
+
+  Ok(command)
+}
+
+pub fn help(prefix: &str, topic: Option<&str>) -> String {
+  if let Some(topic) = topic {
+    let Some(spec) = find(topic.trim_start_matches(prefix)) else {
+      return CommandError::Unknown(topic.to_string()).describe(prefix);
+    };
+
+    let mut help = format!("{} {}", spec.usage(prefix), spec.description);
+    if let Arguments::Settings = spec.arguments {
+      help = format!("{}\n{}", help, SETTINGS_HELP);
+    }
+    return help;
+  }
+
+  let commands: Vec<String> = COMMANDS.iter()
+    .map(|spec| format!("{} {}", spec.usage(prefix), spec.description))
+    .collect();
+  format!("**Words Against Friends commands**\n{}\n{}", commands.join("\n"), SETTINGS_HELP)
+}
+
+// Splits on whitespace, keeping anything inside double quotes together.
+// Phones tend to send curly quotes, so those count too.
+fn tokenize(input: &str) -> Result<Vec<String>, CommandError> {
+  let mut tokens = vec![];
+  let mut current = String::new();
+  let mut in_token = false;
+  let mut quoted = false;
+
+  for c in input.chars() {
+    match c {
+      '"' | '“' | '”' => {
+        quoted = !quoted;
+        in_token = true;
+      }
+      c if c.is_whitespace() && !quoted => {
+        if in_token {
+          tokens.push(mem::take(&mut current));
+          in_token = false;
+        }
+      }
+      c => {
+        current.push(c);
+        in_token = true;
+      }
+    }
+  }
+
+  if quoted {
+    return Err(CommandError::UnclosedQuote);
+  }
+  if in_token {
+    tokens.push(current);
+  }
+  Ok(tokens)
+}
+
+// Discord writes user mentions as `<@id>`, or `<@!id>` for nicknames.
+fn parse_mention(token: &str) -> Option<Id<UserMarker>> {
+  let id = token.strip_prefix("<@")?.strip_suffix('>')?;
+  let id = id.strip_prefix('!').unwrap_or(id);
+
+  Id::new_checked(id.parse().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parsed(input: &str) -> Command {
+    match parse(input) {
+      Ok(command) => command,
+      Err(error) => panic!("`{}` didn't parse: {}", input, error.describe("w::")),
+    }
+  }
+
+  // The name of the command whose usage `input` got wrong.
+  fn usage_error(input: &str) -> &'static str {
+    match parse(input) {
+      Err(CommandError::Usage(spec)) => spec.name,
+      Err(error) => panic!("`{}` failed some other way: {}", input, error.describe("w::")),
+      Ok(_) => panic!("`{}` should have been rejected", input),
+    }
+  }
+
+  #[test]
+  fn quotes_keep_words_together() {
+    assert_eq!(tokenize(r#"set "a b"  c"#).ok(), Some(vec![String::from("set"), String::from("a b"), String::from("c")]));
+    assert_eq!(tokenize("set “a b” c").ok(), Some(vec![String::from("set"), String::from("a b"), String::from("c")]));
+    assert_eq!(tokenize("set “a b\" c=\"\"").ok(), Some(vec![String::from("set"), String::from("a b"), String::from("c=")]));
+    assert!(matches!(tokenize(r#"new "rounds=5"#), Err(CommandError::UnclosedQuote)));
+    assert!(matches!(tokenize("new “rounds=5"), Err(CommandError::UnclosedQuote)));
+  }
+
+  #[test]
+  fn user_mentions_are_parsed() {
+    assert_eq!(parsed("kick <@123>").mentions, vec![Id::new(123)]);
+    assert_eq!(parsed("host <@!456>").mentions, vec![Id::new(456)]);
+  }
+
+  #[test]
+  fn role_mentions_are_not_players() {
+    assert!(parse_mention("<@&123>").is_none());
+    assert_eq!(usage_error("kick <@&123>"), "kick");
+  }
+
+  #[test]
+  fn option_keys_are_lowercased() {
+    let command = parsed("SET Rounds=5 Q=Ten scrabble");
+    assert_eq!(command.options, vec![(String::from("rounds"), String::from("5")), (String::from("q"), String::from("Ten"))]);
+    assert_eq!(command.args, vec![String::from("scrabble")]);
+  }
+
+  #[test]
+  fn commands_only_take_their_own_arguments() {
+    // Nothing
+    assert_eq!(usage_error("join now"), "join");
+    assert_eq!(usage_error("start rounds=5"), "start");
+    assert_eq!(usage_error("leave <@1>"), "leave");
+    // OneMention
+    assert_eq!(usage_error("kick"), "kick");
+    assert_eq!(usage_error("kick <@1> <@2>"), "kick");
+    assert_eq!(usage_error("host bob"), "host");
+    // Settings
+    assert_eq!(usage_error("new <@1>"), "new");
+    // CommandName
+    assert_eq!(usage_error("help new set"), "help");
+    assert_eq!(usage_error("help rounds=5"), "help");
+
+    assert!(parse("help kick").is_ok());
+    assert!(parse("set rounds=5 scrabble").is_ok());
+  }
+
+  #[test]
+  fn usage_errors_explain_the_command() {
+    let Err(error) = parse("kick") else { panic!("`kick` needs a player") };
+    assert_eq!(error.describe("w::"), "Usage: `w::kick @player` Removes a player from the lobby. Host only.");
+  }
+}
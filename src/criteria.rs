@@ -1,6 +1,11 @@
-use crate::words;
+use std::fmt;
+
+use crate::words::{self, Dictionary, PartsOfSpeech};
 use rand::{thread_rng, distributions::WeightedIndex, prelude::Distribution};
 
+const MAX_GENERATION_ATTEMPTS: usize = 50;
+
+#[derive(Clone)]
 pub struct Criteron {
   pattern: Option<String>,
   length: Option<usize>,
@@ -8,8 +13,8 @@ pub struct Criteron {
   criteron_type: CriteronType,
 }
 
-#[derive(PartialEq)]
-enum CriteronType {
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CriteronType {
   StartsWith,
   EndsWith,
   Contains,
@@ -20,53 +25,127 @@ enum CriteronType {
 }
 
 impl Criteron {
-  pub fn test(&self, word: String) -> bool {
+  /// Words starting with `pattern`, for when a round shouldn't be left to chance.
+  pub fn starting_with(pattern: &str) -> Self {
+    Self {
+      pattern: Some(pattern.to_string()),
+      length: None,
+      part_of_speech: None,
+      criteron_type: CriteronType::StartsWith,
+    }
+  }
+
+  pub fn get_type(&self) -> CriteronType {
+    self.criteron_type
+  }
+
+  pub fn test(&self, word: &str, dictionary: &Dictionary) -> bool {
     match &self.criteron_type {
-      CriteronType::StartsWith => word.starts_with(&self.pattern.clone().unwrap()),
-      CriteronType::EndsWith => word.ends_with(&self.pattern.clone().unwrap()),
-      CriteronType::Contains => word.contains(&self.pattern.clone().unwrap()),
+      CriteronType::StartsWith => word.starts_with(self.pattern.as_ref().unwrap().as_str()),
+      CriteronType::EndsWith => word.ends_with(self.pattern.as_ref().unwrap().as_str()),
+      CriteronType::Contains => word.contains(self.pattern.as_ref().unwrap().as_str()),
       CriteronType::OfLength => word.len() == self.length.unwrap(),
       CriteronType::MinLength => word.len() >= self.length.unwrap(),
-      CriteronType::RhymesWith => words::is_rhyme(word, self.pattern.clone().unwrap()),
-      CriteronType::PartOfSpeech => words::is_part_of_speech(word, &self.part_of_speech.clone().unwrap()),
+      CriteronType::RhymesWith => words::is_rhyme(word, self.pattern.as_ref().unwrap()),
+      CriteronType::PartOfSpeech => dictionary.is_part_of_speech(word, self.part_of_speech.as_ref().unwrap()),
     }
   }
-  pub fn to_string(&self) -> String {
+
+  fn candidates<'a>(&'a self, dictionary: &'a Dictionary) -> Option<Box<dyn Iterator<Item = &'a str> + 'a>> {
     match &self.criteron_type {
-      CriteronType::StartsWith => format!("Start with `{}`", &self.pattern.clone().unwrap()),
-      CriteronType::EndsWith => format!("End with `{}`", &self.pattern.clone().unwrap()),
-      CriteronType::Contains => format!("Contain `{}`", &self.pattern.clone().unwrap()),
-      CriteronType::OfLength => format!("Are exactly `{}` letters long", self.length.unwrap()),
-      CriteronType::MinLength => format!("Are at least `{}` letters long", self.length.unwrap()),
-      CriteronType::RhymesWith => format!("Rhyme with \"`{}`\"", &self.pattern.clone().unwrap()),
-      CriteronType::PartOfSpeech => format!("Are `{}`s", self.part_of_speech.clone().unwrap().to_string())
+      CriteronType::StartsWith => Some(Box::new(dictionary.with_prefix(self.pattern.as_ref().unwrap()))),
+      CriteronType::EndsWith => Some(Box::new(dictionary.with_suffix(self.pattern.as_ref().unwrap()))),
+      CriteronType::Contains => Some(Box::new(dictionary.containing(self.pattern.as_ref().unwrap()))),
+      CriteronType::RhymesWith => Some(Box::new(dictionary.rhyming_with(self.pattern.as_ref().unwrap()).into_iter())),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Criteron {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.criteron_type {
+      CriteronType::StartsWith => write!(f, "Start with `{}`", self.pattern.as_ref().unwrap()),
+      CriteronType::EndsWith => write!(f, "End with `{}`", self.pattern.as_ref().unwrap()),
+      CriteronType::Contains => write!(f, "Contain `{}`", self.pattern.as_ref().unwrap()),
+      CriteronType::OfLength => write!(f, "Are exactly `{}` letters long", self.length.unwrap()),
+      CriteronType::MinLength => write!(f, "Are at least `{}` letters long", self.length.unwrap()),
+      CriteronType::RhymesWith => write!(f, "Rhyme with \"`{}`\"", self.pattern.as_ref().unwrap()),
+      CriteronType::PartOfSpeech => write!(f, "Are `{}`s", self.part_of_speech.as_ref().unwrap()),
     }
   }
 }
 
 mod make_criteria {
   use super::*;
-  use rand::{Rng, thread_rng};
+  use rand::{Rng, seq::SliceRandom, thread_rng};
+  use crate::words::NgramPosition;
+
+  // Patterns are drawn from n-grams appearing in this share of the dictionary:
+  // rarer ones make for dead rounds, more common ones ("un", "ing") are trivial.
+  const MIN_PATTERN_SHARE: f64 = 0.0005;
+  const MAX_PATTERN_SHARE: f64 = 0.02;
+  const RHYME_TARGET_LENGTHS: std::ops::RangeInclusive<usize> = 4..=7;
+  const MIN_RHYMES: usize = 30;
+  const MAX_RHYMES: usize = 3000;
+  const MAX_RHYME_TARGET_ATTEMPTS: usize = 100;
+
+  fn random_pattern(dictionary: &Dictionary, position: NgramPosition) -> String {
+    let ngrams = dictionary.ngrams(position);
+    let min = (dictionary.len() as f64 * MIN_PATTERN_SHARE) as usize;
+    let max = (dictionary.len() as f64 * MAX_PATTERN_SHARE) as usize;
 
-  pub fn starts_with() -> Criteron { // TODO: don't hard code lol
+    let mut candidates: Vec<&(String, usize)> = ngrams.iter()
+      .filter(|(_, count)| (min..=max).contains(count))
+      .collect();
+    if candidates.is_empty() {
+      candidates = ngrams.iter().collect();
+    }
+
+    let dist = WeightedIndex::new(candidates.iter().map(|(_, count)| *count))
+      .expect("dictionary has no words long enough to take a pattern from");
+    candidates[dist.sample(&mut thread_rng())].0.clone()
+  }
+
+  fn random_rhyme_target(dictionary: &Dictionary) -> String {
+    let mut rng = thread_rng();
+    let mut target = None;
+
+    for _ in 0..MAX_RHYME_TARGET_ATTEMPTS {
+      let Some(word) = dictionary.random_word(&mut rng) else { break };
+      if !RHYME_TARGET_LENGTHS.contains(&word.len()) {
+        continue;
+      }
+
+      target = Some(word);
+      let partners = dictionary.rhyming_with(word).len() - 1;
+      if (MIN_RHYMES..=MAX_RHYMES).contains(&partners) {
+        break;
+      }
+    }
+
+    target.expect("dictionary has no words to rhyme with").to_string()
+  }
+
+  pub fn starts_with(dictionary: &Dictionary) -> Criteron {
     Criteron {
-      pattern: Some("te".to_owned()),
+      pattern: Some(random_pattern(dictionary, NgramPosition::Start)),
       length: None,
       part_of_speech: None,
       criteron_type: CriteronType::StartsWith,
     }
   }
-  pub fn ends_with() -> Criteron { // TODO: don't hard code lol
+  pub fn ends_with(dictionary: &Dictionary) -> Criteron {
     Criteron {
-      pattern: Some("ge".to_owned()),
+      pattern: Some(random_pattern(dictionary, NgramPosition::End)),
       length: None,
       part_of_speech: None,
       criteron_type: CriteronType::EndsWith,
     }
   }
-  pub fn contains() -> Criteron { // TODO: don't hard code lol
+  pub fn contains(dictionary: &Dictionary) -> Criteron {
     Criteron {
-      pattern: Some("on".to_owned()),
+      pattern: Some(random_pattern(dictionary, NgramPosition::Anywhere)),
       length: None,
       part_of_speech: None,
       criteron_type: CriteronType::Contains,
@@ -92,34 +171,36 @@ mod make_criteria {
         criteron_type: CriteronType::MinLength,
       }
   }
-  pub fn rhymes_with() -> Criteron { // TODO: don't hard code lol
+  pub fn rhymes_with(dictionary: &Dictionary) -> Criteron {
     Criteron {
-      pattern: Some("finally".to_owned()),
+      pattern: Some(random_rhyme_target(dictionary)),
       length: None,
       part_of_speech: None,
       criteron_type: CriteronType::RhymesWith,
     }
   }
-  pub fn part_of_speech() -> Criteron { // TODO: don't hard code lol
+  pub fn part_of_speech() -> Criteron {
+    let mut rng = thread_rng();
+    let part_of_speech = PartsOfSpeech::ALL.choose(&mut rng).unwrap().clone();
     Criteron {
       pattern: None,
       length: None,
-      part_of_speech: Some(words::PartsOfSpeech::Noun),
+      part_of_speech: Some(part_of_speech),
       criteron_type: CriteronType::PartOfSpeech,
     }
   }
 }
 
-fn generate_random_criteron(primary: bool) -> Criteron {
+fn generate_random_criteron(primary: bool, dictionary: &Dictionary) -> Criteron {
   let mut rng = thread_rng();
 
   if primary {
     let criteria_dist = WeightedIndex::new([50, 40, 20]).unwrap();
 
     match criteria_dist.sample(&mut rng) {
-      0 => make_criteria::starts_with(),
-      1 => make_criteria::ends_with(),
-      2 => make_criteria::contains(),
+      0 => make_criteria::starts_with(dictionary),
+      1 => make_criteria::ends_with(dictionary),
+      2 => make_criteria::contains(dictionary),
       _ => panic!()
     }
   } else {
@@ -129,21 +210,21 @@ fn generate_random_criteron(primary: bool) -> Criteron {
     match selected {
       0 => make_criteria::of_length(),
       1 => make_criteria::min_length(),
-      2 => make_criteria::rhymes_with(),
+      2 => make_criteria::rhymes_with(dictionary),
       3 => make_criteria::part_of_speech(),
       _ => panic!()
     }
   }
 }
 
-pub fn generate_random_criteria() -> Vec<Criteron> {
+fn generate_candidate_criteria(dictionary: &Dictionary) -> Vec<Criteron> {
   let mut rng = thread_rng();
 
   let num_criteria_dist = WeightedIndex::new([100, 50]).unwrap();
   let num_criteria = num_criteria_dist.sample(&mut rng);
 
-  let first = generate_random_criteron(true);
-  let second = generate_random_criteron(false);
+  let first = generate_random_criteron(true, dictionary);
+  let second = generate_random_criteron(false, dictionary);
   if first.criteron_type == CriteronType::EndsWith && second.criteron_type == CriteronType::RhymesWith {
     return vec![second];
   }
@@ -154,3 +235,57 @@ pub fn generate_random_criteria() -> Vec<Criteron> {
     _ => panic!()
   }
 }
+
+/// How many dictionary words a round's criteria should admit. Sets outside
+/// these bounds are thrown away and regenerated.
+pub struct AnswerBounds {
+  pub min: usize,
+  pub max: usize,
+}
+
+impl AnswerBounds {
+  fn distance(&self, answers: usize) -> usize {
+    if answers < self.min {
+      self.min - answers
+    } else {
+      answers.saturating_sub(self.max)
+    }
+  }
+}
+
+pub fn count_answers(criteria: &[Criteron], dictionary: &Dictionary) -> usize {
+  answers(criteria, dictionary).len()
+}
+
+/// Every dictionary word that meets all of `criteria`.
+pub fn answers<'a>(criteria: &'a [Criteron], dictionary: &'a Dictionary) -> Vec<&'a str> {
+  let candidates = criteria.iter()
+    .find_map(|criteron| criteron.candidates(dictionary))
+    .unwrap_or_else(|| Box::new(dictionary.iter()));
+
+  candidates.filter(|word| criteria.iter().all(|criteron| criteron.test(word, dictionary))).collect()
+}
+
+pub fn generate_random_criteria(dictionary: &Dictionary, bounds: &AnswerBounds) -> Vec<Criteron> {
+  let mut closest: Option<(Vec<Criteron>, usize)> = None;
+
+  for _ in 0..MAX_GENERATION_ATTEMPTS {
+    let criteria = generate_candidate_criteria(dictionary);
+    let distance = bounds.distance(count_answers(&criteria, dictionary));
+    if distance == 0 {
+      return criteria;
+    }
+
+    if closest.as_ref().is_none_or(|(_, closest_distance)| distance < *closest_distance) {
+      closest = Some((criteria, distance));
+    }
+  }
+
+  closest.unwrap().0
+}
+
+// Extra criteria kept secret during the round; words that happen to meet them
+// earn a bonus.
+pub fn generate_hidden_criteria(dictionary: &Dictionary, count: usize) -> Vec<Criteron> {
+  (0..count).map(|_| generate_random_criteron(false, dictionary)).collect()
+}
@@ -1,152 +1,905 @@
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
-use twilight_model::{channel::Message, id::{marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker}, Id}};
-use twilight_http::{Client as HttpClient, request::channel::reaction::RequestReactionType, Response, response::marker::EmptyBody};
-use crate::game::{WordsAgainstStrangers, GameState};
+use std::{collections::HashMap, fmt, future::IntoFuture, sync::{Arc, OnceLock}, time::{SystemTime, UNIX_EPOCH}};
+use tokio::{sync::{mpsc::UnboundedSender, Mutex}, time::{sleep, Duration, Instant}};
+use twilight_model::{
+  application::interaction::{Interaction, InteractionData, InteractionType},
+  channel::{message::{component::Component, embed::Embed, MessageFlags}, Message},
+  guild::Permissions,
+  http::interaction::{InteractionResponse, InteractionResponseType},
+  id::{marker::{ApplicationMarker, ChannelMarker, GuildMarker, InteractionMarker, MessageMarker, RoleMarker, UserMarker}, Id},
+};
+use twilight_http::{api_error::ApiError, error::{Error as HttpError, ErrorType}, request::channel::reaction::RequestReactionType, Client as HttpClient, Response};
+use twilight_util::builder::InteractionResponseDataBuilder;
+use crate::{
+  command::{self, Command, CommandKind},
+  criteria::Criteron,
+  game::{GameState, LobbySettings, WordsAgainstStrangers},
+  round::{RoundSummary, WordResult},
+  scoreboard,
+  settings::GameSettings,
+  slash,
+  transport::{GameTransport, PlayerId, Submission, TransportError, WordFeedback},
+  words::Dictionary,
+};
+
+/// Things that happen to games outside of a Discord event, reported back to the
+/// bot's event loop.
+pub enum BotEvent {
+  GameFinished(Id<ChannelMarker>),
+  // Lobby timers carry the lobby's ID so a timer left over from a cancelled
+  // lobby can't touch a newer one in the same channel.
+  LobbyExpiryCheck { channel_id: Id<ChannelMarker>, lobby_id: u64 },
+  AutoStart { channel_id: Id<ChannelMarker>, lobby_id: u64 },
+  // Someone other than the host tried to cancel, and Discord has said whether
+  // they're a moderator.
+  CancelChecked { ctx: CommandContext, is_moderator: bool },
+}
 
 pub struct DiscordBot {
   prefix: String,
-  games: HashMap<Id<GuildMarker>, Arc<Mutex<WordsAgainstStrangers>>>,
-  dm_to_guild: HashMap<Id<UserMarker>, Id<GuildMarker>>,
+  // Games by the channel they were opened in.
+  games: HashMap<Id<ChannelMarker>, GameSlot>,
+  // Players can only be in one game at a time, so this is also where their
+  // DMs go.
+  player_games: HashMap<Id<UserMarker>, Id<ChannelMarker>>,
+  max_games_per_guild: usize,
   minion: DiscordMinion,
-  token: String,
+  dictionary: Arc<Dictionary>,
+  lobby: LobbySettings,
+  // Criteria for every round of every game, in place of random ones.
+  criteria: Option<Vec<Criteron>>,
+  next_lobby_id: u64,
+  // Known once the slash commands are registered.
+  application_id: Option<Id<ApplicationMarker>>,
+  bot_events: UnboundedSender<BotEvent>,
 }
 
 impl DiscordBot {
-  pub fn new(prefix: String, token: String) -> Self {
+  pub fn new(
+    prefix: String,
+    client: HttpClient,
+    dictionary: Arc<Dictionary>,
+    lobby: LobbySettings,
+    max_games_per_guild: usize,
+    bot_events: UnboundedSender<BotEvent>,
+  ) -> Self {
     Self {
       prefix,
       games: HashMap::new(),
-      dm_to_guild: HashMap::new(),
-      minion: DiscordMinion::new(token.clone()),
-      token,
+      player_games: HashMap::new(),
+      max_games_per_guild,
+      minion: DiscordMinion::new(client),
+      dictionary,
+      lobby,
+      criteria: None,
+      next_lobby_id: 0,
+      application_id: None,
+      bot_events,
     }
   }
 
-  pub async fn handle_message(&mut self, message: Message) {
-    if self.dm_to_guild.contains_key(&message.author.id) && message.guild_id.is_none() {
-      println!("got word '{}'", message.content);
-      let guild_id = self.dm_to_guild.get(&message.author.id).unwrap();
-      println!("guild {}", guild_id);
-      let mut relevant_game = self.games.get_mut(guild_id).unwrap().lock().await;
-      relevant_game.receive_word(&message, message.content.clone()).await;
+  pub async fn handle_bot_event(&mut self, event: BotEvent) {
+    match event {
+      BotEvent::GameFinished(channel_id) => self.remove_game(channel_id),
+      BotEvent::LobbyExpiryCheck { channel_id, lobby_id } => {
+        if !self.is_open_lobby(channel_id, lobby_id).await {
+          return;
+        }
+
+        let deadline = self.get_game(channel_id).await.get_lobby_deadline();
+        let now = Instant::now();
+        if deadline > now {
+          self.schedule(deadline - now, BotEvent::LobbyExpiryCheck { channel_id, lobby_id });
+          return;
+        }
+
+        let mut game = self.get_game(channel_id).await;
+        let minutes = game.get_lobby_settings().expire_after.as_secs() / 60;
+        let reason = format!("This lobby closed after {} minutes without any activity.", minutes);
+        game.cancel(&reason);
+        drop(game);
+        self.refresh_header(channel_id);
+        let minion = self.minion.clone();
+        tokio::task::spawn(async move {
+          log_failure(minion.send_message(channel_id, reason).await, "say why a lobby closed");
+        });
+        self.remove_game(channel_id);
+      }
+      BotEvent::AutoStart { channel_id, lobby_id } => {
+        if self.is_open_lobby(channel_id, lobby_id).await {
+          self.begin_game(channel_id).await;
+        }
+      }
+      BotEvent::CancelChecked { ctx, is_moderator } => {
+        if !is_moderator {
+          self.reply(&ctx, CommonMessages::NoCancelPermission.val());
+          return;
+        }
+        // The lobby might have started or closed while we were asking.
+        if self.check_lobby(&ctx).await {
+          self.end_lobby(&ctx).await;
+        }
+      }
     }
+  }
 
-    if message.content.clone().starts_with(&self.prefix) {
-      if message.guild_id.is_none() {
-        self.minion.send_message(message.channel_id, CommonMessages::NoDmCommands.val()).await;
+  pub async fn handle_message(&mut self, message: Message) {
+    if let Some(channel_id) = self.player_games.get(&message.author.id) {
+      let slot = self.games.get(channel_id).unwrap();
+      // Words come in by DM, or in the game's channel or thread in open play.
+      let in_play = message.guild_id.is_none() || slot.transport.get_play_channel() == Some(message.channel_id);
+      if in_play && !message.content.starts_with(&self.prefix) {
+        println!("got word '{}' for channel {}", message.content, channel_id);
+        // Scored off the event loop, so a busy game can't hold up the others.
+        let game = slot.game.clone();
+        let transport = slot.transport.clone();
+        tokio::task::spawn(async move {
+          let submission = Submission {
+            player: message.author.id.into(),
+            word: message.content.clone(),
+            openly: message.guild_id.is_some(),
+          };
+          let feedback = game.lock().await.receive_word(&submission);
+          if let Some(feedback) = feedback {
+            transport.acknowledge(&message, &feedback).await;
+          }
+        });
         return;
       }
+    }
 
-      let prefix_removed = message.content.strip_prefix(&self.prefix).unwrap().to_string();
-      let mut chunks: Vec<&str> = prefix_removed.split(" ").collect();
-      let command = chunks.pop().unwrap();
+    let Some(input) = message.content.strip_prefix(&self.prefix) else {
+      return;
+    };
+    let ctx = CommandContext::from_message(&message);
+    match command::parse(input) {
+      Ok(command) => self.run_command(&ctx, command).await,
+      Err(error) => self.reply(&ctx, error.describe(&self.prefix)),
+    }
+  }
 
-      match command {
-        "new" => { self.new_game(message).await; }
-        "join" => { self.join_game(message).await; }
-        "start" => { self.start_game(message).await; }
-        _ => {}
+  pub async fn handle_interaction(&mut self, interaction: Interaction) {
+    let command = match &interaction.data {
+      Some(InteractionData::ApplicationCommand(data)) => slash::parse(data),
+      Some(InteractionData::MessageComponent(data)) => slash::parse_button(data),
+      _ => None,
+    };
+    let Some(command) = command else {
+      return;
+    };
+
+    let ctx = CommandContext::from_interaction(&interaction);
+    self.run_command(&ctx, command).await;
+  }
+
+  /// Plays every round of every game with `criteria`, so tests know which
+  /// words score.
+  #[doc(hidden)]
+  pub fn with_criteria(mut self, criteria: Vec<Criteron>) -> Self {
+    self.criteria = Some(criteria);
+    self
+  }
+
+  /// Registers the slash commands, replacing whatever was registered before.
+  pub async fn register_commands(&mut self) -> anyhow::Result<()> {
+    let application = self.minion.client.current_user_application().await?.model().await?;
+    self.minion.client.interaction(application.id).set_global_commands(&slash::commands()).await?;
+    self.application_id = Some(application.id);
+
+    Ok(())
+  }
+
+  async fn run_command(&mut self, ctx: &CommandContext, command: Command) {
+    if command.spec.kind == CommandKind::Help {
+      let help = command::help(&self.prefix, command.args.first().map(String::as_str));
+      self.reply(ctx, help);
+      return;
+    }
+    if ctx.guild_id.is_none() {
+      self.reply(ctx, CommonMessages::NoDmCommands.val());
+      return;
+    }
+
+    match command.spec.kind {
+      CommandKind::New => { self.new_game(ctx, &command).await; }
+      CommandKind::Join => { self.join_game(ctx).await; }
+      CommandKind::Start => { self.start_game(ctx).await; }
+      CommandKind::Leave => { self.leave_game(ctx).await; }
+      CommandKind::Kick => { self.kick_player(ctx, command.mentions[0]).await; }
+      CommandKind::Cancel => { self.cancel_game(ctx).await; }
+      CommandKind::Host => { self.transfer_host(ctx, command.mentions[0]).await; }
+      CommandKind::Set => { self.change_settings(ctx, &command).await; }
+      CommandKind::Help => {}
+    }
+  }
+
+  // Prefix commands are answered in the channel; slash commands only show
+  // their answers to whoever ran them. Like everything else sent to Discord
+  // from here, answers are sent off the event loop.
+  fn reply(&self, ctx: &CommandContext, content: String) {
+    let minion = self.minion.clone();
+    let application_id = self.application_id;
+    let channel_id = ctx.channel_id;
+    let source = ctx.source.clone();
+    tokio::task::spawn(async move {
+      let replied = match source {
+        CommandSource::Message(_) => minion.send_message(channel_id, content).await.map(|_| ()),
+        CommandSource::Interaction { id, token, .. } => minion.respond_privately(application_id.unwrap(), id, &token, content).await,
+      };
+      log_failure(replied, "reply to a command");
+    });
+  }
+
+  // Interactions have to be answered within three seconds, so commands answer
+  // before anything else about them is sent.
+  fn acknowledge(&self, ctx: &CommandContext) {
+    let minion = self.minion.clone();
+    let application_id = self.application_id;
+    let channel_id = ctx.channel_id;
+    match ctx.source.clone() {
+      CommandSource::Message(message_id) => {
+        tokio::task::spawn(async move {
+          let reacted = minion.add_reaction(channel_id, message_id, CommonReactions::CheckmarkGreen).await;
+          log_failure(reacted, "acknowledge a command");
+        });
       }
+      // The header the button sits on already shows what changed.
+      CommandSource::Interaction { id, token, from_button: true } => {
+        tokio::task::spawn(async move {
+          log_failure(minion.respond_silently(application_id.unwrap(), id, &token).await, "acknowledge a button");
+        });
+      }
+      CommandSource::Interaction { .. } => self.reply(ctx, String::from("✅")),
+    }
+  }
+
+  // Shows the game's latest state in its header, once the game's unlocked.
+  fn refresh_header(&self, channel_id: Id<ChannelMarker>) {
+    let game = self.games.get(&channel_id).unwrap().game.clone();
+    tokio::task::spawn(async move {
+      WordsAgainstStrangers::update_header(&game).await;
+    });
+  }
+
+  async fn new_game(&mut self, ctx: &CommandContext, command: &Command) {
+    let guild_id = ctx.guild_id.unwrap();
+    if self.games.contains_key(&ctx.channel_id) {
+      self.reply(ctx, CommonMessages::ExistingGame.val());
+      return;
+    }
+    if self.games.values().filter(|slot| slot.guild_id == guild_id).count() >= self.max_games_per_guild {
+      self.reply(ctx, CommonMessages::TooManyGames.val());
+      return;
+    }
+    if self.player_games.contains_key(&ctx.author) {
+      self.reply(ctx, CommonMessages::AlreadyInGame.val());
+      return;
+    }
+
+    let mut settings = GameSettings::default();
+    if let Err(error) = settings.apply(&command.args, &command.options) {
+      self.reply(ctx, error);
+      return;
+    }
+
+    let lobby_id = self.next_lobby_id;
+    self.next_lobby_id += 1;
+    let transport = DiscordTransport::new(self.minion.clone(), self.prefix.clone(), ctx.channel_id);
+    let mut new_game = WordsAgainstStrangers::new(
+      lobby_id,
+      self.lobby.clone(),
+      ctx.author.into(),
+      transport.clone(),
+      self.dictionary.clone(),
+      settings,
+    );
+    if let Some(criteria) = &self.criteria {
+      new_game = new_game.with_criteria(criteria.clone());
+    }
+    let channel_id = ctx.channel_id;
+    self.games.insert(channel_id, GameSlot { guild_id, game: Arc::new(Mutex::new(new_game)), transport });
+    self.player_games.insert(ctx.author, channel_id);
+    self.acknowledge(ctx);
+    self.refresh_header(channel_id);
+
+    self.schedule(self.lobby.expire_after, BotEvent::LobbyExpiryCheck { channel_id, lobby_id });
+    if let Some(start_after) = self.lobby.start_after {
+      self.schedule(start_after, BotEvent::AutoStart { channel_id, lobby_id });
     }
   }
+  async fn join_game(&mut self, ctx: &CommandContext) {
+    if !self.check_lobby(ctx).await {
+      return;
+    }
+    if self.player_games.contains_key(&ctx.author) {
+      self.reply(ctx, CommonMessages::AlreadyInGame.val());
+      return;
+    }
+
+    self.get_game(ctx.channel_id).await.add_player(ctx.author.into());
+    self.player_games.insert(ctx.author, ctx.channel_id);
+    self.acknowledge(ctx);
+    self.refresh_header(ctx.channel_id);
 
-  async fn new_game(&mut self, message: Message) {
-    if self.games.contains_key(&message.guild_id.unwrap()) {
-      self.minion.send_message(message.channel_id, CommonMessages::ExistingGame.val()).await;
+    let num_players = self.get_game(ctx.channel_id).await.get_players().len();
+    if self.lobby.start_at_players.is_some_and(|players| num_players >= players) {
+      self.begin_game(ctx.channel_id).await;
+    }
+  }
+  async fn start_game(&mut self, ctx: &CommandContext) {
+    if !self.check_lobby(ctx).await {
       return;
     }
-    if self.dm_to_guild.contains_key(&message.author.id) {
-      self.minion.send_message(message.channel_id, CommonMessages::AlreadyInGame.val()).await;
+    if self.get_game(ctx.channel_id).await.get_host() != ctx.author.into() {
+      self.reply(ctx, CommonMessages::NoPermission.val());
       return;
     }
 
-    let new_game = WordsAgainstStrangers::new(
-      message.channel_id,
-      message.author.id,
-      DiscordMinion::new(self.token.clone())
-    ).await;
-    self.games.insert(message.guild_id.unwrap(), Arc::new(Mutex::new(new_game)));
+    self.acknowledge(ctx);
+    self.begin_game(ctx.channel_id).await;
   }
-  async fn join_game(&mut self, message: Message) {
-    if !self.games.contains_key(&message.guild_id.unwrap()) {
-      self.minion.send_message(message.channel_id, CommonMessages::NoExistingGame.val()).await;
+  async fn begin_game(&mut self, channel_id: Id<ChannelMarker>) {
+    let game = self.games.get(&channel_id).unwrap().game.clone();
+    game.lock().await.close_lobby();
+    println!("started starting game in channel {}!", channel_id);
+
+    let transport = self.games.get(&channel_id).unwrap().transport.clone();
+    let bot_events = self.bot_events.clone();
+    tokio::task::spawn(async move {
+      // The game plays in a task of its own, so it's cleaned up after even if
+      // it panics, rather than leaving the channel and its players stuck in it.
+      if let Err(error) = tokio::task::spawn(WordsAgainstStrangers::run(game)).await {
+        println!("game in channel {} crashed: {}", channel_id, error);
+        transport.announce(String::from("**Words Against Friends**\nSomething went wrong, so this game had to end. Sorry!")).await;
+      }
+      bot_events.send(BotEvent::GameFinished(channel_id)).ok();
+    });
+    println!("finished starting game!");
+  }
+
+  async fn leave_game(&mut self, ctx: &CommandContext) {
+    if !self.check_lobby(ctx).await {
+      return;
+    }
+    if !self.get_game(ctx.channel_id).await.is_player(ctx.author.into()) {
+      self.reply(ctx, CommonMessages::NotInGame.val());
       return;
     }
 
-    if self.get_game(&message).await.get_state() != GameState::Starting {
-      self.minion.send_message(message.channel_id, CommonMessages::GameInProgress.val()).await;
+    self.player_games.remove(&ctx.author);
+    let anyone_left = self.get_game(ctx.channel_id).await.remove_player(ctx.author.into());
+    if anyone_left {
+      self.acknowledge(ctx);
+      self.refresh_header(ctx.channel_id);
+    } else {
+      self.reply(ctx, CommonMessages::LastPlayerLeft.val());
+      self.get_game(ctx.channel_id).await.cancel(&CommonMessages::LastPlayerLeft.val());
+      self.refresh_header(ctx.channel_id);
+      self.remove_game(ctx.channel_id);
+    }
+  }
+  async fn kick_player(&mut self, ctx: &CommandContext, target: Id<UserMarker>) {
+    if !self.check_lobby(ctx).await {
+      return;
+    }
+    if self.get_game(ctx.channel_id).await.get_host() != ctx.author.into() {
+      self.reply(ctx, CommonMessages::NotHost.val());
+      return;
+    }
+    if target == ctx.author {
+      self.reply(ctx, format!("You can't kick yourself, use `{0}leave` or `{0}cancel` instead.", self.prefix));
       return;
     }
-    if self.dm_to_guild.contains_key(&message.author.id) {
-      self.minion.send_message(message.channel_id, CommonMessages::AlreadyInGame.val()).await;
+    if !self.get_game(ctx.channel_id).await.is_player(target.into()) {
+      self.reply(ctx, CommonMessages::MentionNotInGame.val());
       return;
     }
 
-    self.get_game(&message).await.add_player(message.author.id).await;
-    self.minion.add_reaction(&message, CommonReactions::CheckmarkGreen).await;
+    self.get_game(ctx.channel_id).await.remove_player(target.into());
+    self.player_games.remove(&target);
+    self.acknowledge(ctx);
+    self.refresh_header(ctx.channel_id);
   }
-  async fn start_game(&mut self, message: Message) {
-    if !self.games.contains_key(&message.guild_id.unwrap()) {
-      self.minion.send_message(message.channel_id, CommonMessages::NoExistingGame.val()).await;
+  async fn cancel_game(&mut self, ctx: &CommandContext) {
+    if !self.check_lobby(ctx).await {
       return;
     }
-    if self.get_game(&message).await.get_state() != GameState::Starting {
-      self.minion.send_message(message.channel_id, CommonMessages::GameInProgress.val()).await;
+    if self.get_game(ctx.channel_id).await.get_host() == ctx.author.into() {
+      self.end_lobby(ctx).await;
       return;
     }
-    if self.get_game(&message).await.get_players().get(0).unwrap() != &message.author.id {
-      self.minion.send_message(message.channel_id, CommonMessages::NoPermission.val()).await;
+
+    // Asking Discord who the moderators are can take a while if it's
+    // struggling, so the answer comes back to the event loop as an event.
+    let minion = self.minion.clone();
+    let bot_events = self.bot_events.clone();
+    let ctx = ctx.clone();
+    tokio::task::spawn(async move {
+      // If Discord can't tell us who the moderators are, only the host may cancel.
+      let is_moderator = match minion.is_moderator(ctx.guild_id.unwrap(), ctx.author, &ctx.roles).await {
+        Ok(is_moderator) => is_moderator,
+        Err(error) => {
+          println!("couldn't check whether {} is a moderator: {}", ctx.author, error);
+          false
+        }
+      };
+      bot_events.send(BotEvent::CancelChecked { ctx, is_moderator }).ok();
+    });
+  }
+  async fn end_lobby(&mut self, ctx: &CommandContext) {
+    self.acknowledge(ctx);
+    self.get_game(ctx.channel_id).await.cancel("This game was cancelled.");
+    self.refresh_header(ctx.channel_id);
+    self.remove_game(ctx.channel_id);
+  }
+  async fn transfer_host(&mut self, ctx: &CommandContext, target: Id<UserMarker>) {
+    if !self.check_lobby(ctx).await {
+      return;
+    }
+    if self.get_game(ctx.channel_id).await.get_host() != ctx.author.into() {
+      self.reply(ctx, CommonMessages::NotHost.val());
+      return;
+    }
+    if !self.get_game(ctx.channel_id).await.is_player(target.into()) {
+      self.reply(ctx, CommonMessages::MentionNotInGame.val());
+      return;
+    }
+
+    self.get_game(ctx.channel_id).await.set_host(target.into());
+    self.acknowledge(ctx);
+    self.refresh_header(ctx.channel_id);
+  }
+  async fn change_settings(&mut self, ctx: &CommandContext, command: &Command) {
+    if !self.check_lobby(ctx).await {
+      return;
+    }
+    if command.args.is_empty() && command.options.is_empty() {
+      let settings = self.get_game(ctx.channel_id).await.get_settings().to_string();
+      self.reply(ctx, settings);
+      return;
+    }
+    if self.get_game(ctx.channel_id).await.get_host() != ctx.author.into() {
+      self.reply(ctx, CommonMessages::NotHost.val());
+      return;
+    }
+
+    let mut settings = self.get_game(ctx.channel_id).await.get_settings().clone();
+    if let Err(error) = settings.apply(&command.args, &command.options) {
+      self.reply(ctx, error);
       return;
     }
 
-    let players = self.get_game(&message).await.get_players().clone();
-    for player in players {
-      self.dm_to_guild.insert(player, message.guild_id.unwrap());
+    self.get_game(ctx.channel_id).await.update_settings(settings);
+    self.acknowledge(ctx);
+    self.refresh_header(ctx.channel_id);
+  }
+
+  // Lobby commands need a game in this channel that hasn't started yet.
+  async fn check_lobby(&self, ctx: &CommandContext) -> bool {
+    if !self.games.contains_key(&ctx.channel_id) {
+      self.reply(ctx, CommonMessages::NoExistingGame.val());
+      return false;
     }
-    println!("started starting game! {:?}", self.dm_to_guild);
+    if self.get_game(ctx.channel_id).await.get_state() != GameState::Starting {
+      self.reply(ctx, CommonMessages::GameInProgress.val());
+      return false;
+    }
+
+    true
+  }
+
+  async fn is_open_lobby(&self, channel_id: Id<ChannelMarker>, lobby_id: u64) -> bool {
+    let Some(GameSlot { game, .. }) = self.games.get(&channel_id) else {
+      return false;
+    };
+    let game = game.lock().await;
 
-    let game = self.games.get(&message.guild_id.unwrap()).unwrap().clone();
+    game.get_lobby_id() == lobby_id && game.get_state() == GameState::Starting
+  }
+
+  fn schedule(&self, delay: Duration, event: BotEvent) {
+    let bot_events = self.bot_events.clone();
     tokio::task::spawn(async move {
-      game.lock().await.start().await;
+      sleep(delay).await;
+      bot_events.send(event).ok();
     });
-    println!("finished starting game!");
   }
 
-  async fn get_game(&self, message: &Message) -> tokio::sync::MutexGuard<'_, WordsAgainstStrangers, > {
-    self.games.get(&message.guild_id.unwrap()).unwrap().lock().await
+  fn remove_game(&mut self, channel_id: Id<ChannelMarker>) {
+    self.games.remove(&channel_id);
+    self.player_games.retain(|_, game_channel| *game_channel != channel_id);
+    println!("cleaned up game in channel {}", channel_id);
+  }
+
+  async fn get_game(&self, channel_id: Id<ChannelMarker>) -> tokio::sync::MutexGuard<'_, WordsAgainstStrangers<DiscordTransport>> {
+    self.games.get(&channel_id).unwrap().game.lock().await
+  }
+}
+
+struct GameSlot {
+  guild_id: Id<GuildMarker>,
+  game: Arc<Mutex<WordsAgainstStrangers<DiscordTransport>>>,
+  // Kept out here too, so words can be routed without waiting on the game.
+  transport: DiscordTransport,
+}
+
+impl From<Id<UserMarker>> for PlayerId {
+  fn from(user: Id<UserMarker>) -> Self {
+    PlayerId(user.get())
+  }
+}
+
+impl From<PlayerId> for Id<UserMarker> {
+  fn from(player: PlayerId) -> Self {
+    Id::new(player.0)
+  }
+}
+
+/// Plays a game in the Discord channel it was opened in.
+#[derive(Clone)]
+pub struct DiscordTransport {
+  minion: DiscordMinion,
+  prefix: String,
+  public_channel: Id<ChannelMarker>,
+  // Shared between clones, so the game and the bot agree on where things are.
+  header_message: Arc<OnceLock<Id<MessageMarker>>>,
+  play_channel: Arc<OnceLock<Id<ChannelMarker>>>,
+}
+
+impl DiscordTransport {
+  fn new(minion: DiscordMinion, prefix: String, public_channel: Id<ChannelMarker>) -> Self {
+    Self {
+      minion,
+      prefix,
+      public_channel,
+      header_message: Arc::new(OnceLock::new()),
+      play_channel: Arc::new(OnceLock::new()),
+    }
+  }
+
+  /// Where words are sent in the open, once play has opened.
+  pub fn get_play_channel(&self) -> Option<Id<ChannelMarker>> {
+    self.play_channel.get().copied()
+  }
+}
+
+impl GameTransport for DiscordTransport {
+  type Receipt = Message;
+
+  fn mention(&self, player: PlayerId) -> String {
+    format!("<@!{}>", player)
+  }
+
+  fn countdown(&self, to: SystemTime) -> String {
+    // Discord renders this as a live countdown in each reader's timezone.
+    format!("<t:{}:R>", to.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+  }
+
+  fn new_game_hint(&self) -> String {
+    format!("Start a new one with `{}new`.", self.prefix)
+  }
+
+  async fn set_header(&self, content: String, open: bool) {
+    let buttons = match open {
+      true => slash::lobby_buttons(),
+      false => vec![],
+    };
+    match self.header_message.get() {
+      Some(header) => {
+        let edited = self.minion.edit_message_and_components(self.public_channel, *header, content, &buttons).await;
+        log_failure(edited, "update the lobby header");
+      }
+      None => match self.minion.send_message_with_components(self.public_channel, content, &buttons).await {
+        Ok(header) => { self.header_message.set(header.id).ok(); }
+        Err(error) => println!("couldn't post the lobby header in channel {}: {}", self.public_channel, error),
+      },
+    }
+  }
+
+  async fn announce(&self, content: String) {
+    log_failure(self.minion.send_message(self.public_channel, content).await, "announce in the game channel");
+  }
+
+  async fn open_play(&self, separate: bool) -> Result<String, TransportError> {
+    if let Some(play_channel) = self.get_play_channel() {
+      return Ok(format!("<#{}>", play_channel));
+    }
+
+    let play_channel = match separate {
+      // Threads are started from the lobby header, so there has to be one.
+      true => {
+        let header = self.header_message.get().ok_or(TransportError::Failed)?;
+        self.minion.start_thread(self.public_channel, *header, "Words Against Friends").await?
+      }
+      false => self.public_channel,
+    };
+    self.play_channel.set(play_channel).ok();
+    Ok(format!("<#{}>", play_channel))
+  }
+
+  async fn announce_in_play(&self, content: String) {
+    if let Some(play_channel) = self.get_play_channel() {
+      log_failure(self.minion.send_message(play_channel, content).await, "announce where the game is played");
+    }
+  }
+
+  async fn message_player(&self, player: PlayerId, content: String) -> Result<(), TransportError> {
+    self.minion.dm(player.into(), &content).await?;
+    Ok(())
+  }
+
+  async fn acknowledge(&self, message: &Message, feedback: &WordFeedback) {
+    let reaction = match feedback.result {
+      WordResult::Invalid => CommonReactions::RedX,
+      WordResult::Blocked { .. } => CommonReactions::OctagonalSign,
+      WordResult::AlreadyUsed => CommonReactions::Repeat,
+      WordResult::Scored => CommonReactions::CheckmarkGreen,
+      WordResult::ScoredBonus => CommonReactions::CheckmarkBlue,
+    };
+    if !feedback.hide {
+      log_failure(self.minion.add_reaction(message.channel_id, message.id, reaction).await, "react to a word");
+      if let Some(explanation) = &feedback.explanation {
+        log_failure(self.minion.send_message(message.channel_id, explanation.clone()).await, "explain a word's result");
+      }
+      return;
+    }
+
+    // Deleting other people's messages needs Manage Messages, which servers
+    // might not have given us. The guess has been scored either way.
+    log_failure(self.minion.delete_message(message.channel_id, message.id).await, "hide a word");
+    // A reaction would go with the message, so the verdict goes by DM instead.
+    let mut verdict = format!("{} `{}`", reaction.emoji(), message.content.trim().to_lowercase());
+    if let Some(explanation) = &feedback.explanation {
+      verdict = format!("{}\n{}", verdict, explanation);
+    }
+    match self.minion.dm(message.author.id, &verdict).await {
+      Ok(_) => {}
+      // Players who don't take DMs get a verdict that doesn't give the word away.
+      Err(MinionError::Forbidden) => {
+        let mut verdict = format!("{} {}", self.mention(message.author.id.into()), reaction.emoji());
+        if let Some(explanation) = &feedback.explanation {
+          verdict = format!("{}\n{}", verdict, explanation);
+        }
+        log_failure(self.minion.send_message(message.channel_id, verdict).await, "give a hidden word's result");
+      }
+      Err(error) => println!("couldn't DM a hidden word's result: {}", error),
+    }
+  }
+
+  async fn show_round_results(&self, round_number: u32, num_rounds: u32, summary: &RoundSummary) {
+    let results = scoreboard::round_results(round_number, num_rounds, summary);
+    log_failure(self.minion.send_embed(self.public_channel, results).await, "show a round's results");
+  }
+
+  async fn show_final_results(&self, summary: &RoundSummary) {
+    log_failure(self.minion.send_embed(self.public_channel, scoreboard::final_results(summary)).await, "show the final results");
+  }
+}
+
+/// Who ran a command and where, whether they typed it or used a slash command.
+#[derive(Clone)]
+pub struct CommandContext {
+  author: Id<UserMarker>,
+  guild_id: Option<Id<GuildMarker>>,
+  channel_id: Id<ChannelMarker>,
+  roles: Vec<Id<RoleMarker>>,
+  source: CommandSource,
+}
+
+#[derive(Clone)]
+enum CommandSource {
+  Message(Id<MessageMarker>),
+  // Slash commands have to be answered through the interaction itself.
+  Interaction { id: Id<InteractionMarker>, token: String, from_button: bool },
+}
+
+impl CommandContext {
+  fn from_message(message: &Message) -> Self {
+    Self {
+      author: message.author.id,
+      guild_id: message.guild_id,
+      channel_id: message.channel_id,
+      roles: message.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default(),
+      source: CommandSource::Message(message.id),
+    }
+  }
+
+  fn from_interaction(interaction: &Interaction) -> Self {
+    Self {
+      author: interaction.author_id().unwrap(),
+      guild_id: interaction.guild_id,
+      channel_id: interaction.channel_id.unwrap(),
+      roles: interaction.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default(),
+      source: CommandSource::Interaction {
+        id: interaction.id,
+        token: interaction.token.clone(),
+        from_button: interaction.kind == InteractionType::MessageComponent,
+      },
+    }
+  }
+}
+
+// Requests that fail in a way that might not happen again are retried this
+// many times in all, waiting twice as long after each failure.
+const MAX_ATTEMPTS: u32 = 4;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Why a request to Discord didn't go through.
+#[derive(Debug)]
+pub enum MinionError {
+  /// Discord said no, e.g. a user's DMs are closed or a permission is missing.
+  Forbidden,
+  NotFound,
+  /// Discord kept failing or rate limiting us, even after retrying.
+  Unavailable,
+  /// The request was wrong in a way retrying won't fix.
+  Invalid(String),
+}
+
+impl MinionError {
+  fn invalid(error: impl fmt::Display) -> Self {
+    Self::Invalid(error.to_string())
+  }
+}
+
+impl fmt::Display for MinionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Forbidden => f.write_str("Discord refused the request"),
+      Self::NotFound => f.write_str("Discord couldn't find what the request was for"),
+      Self::Unavailable => write!(f, "Discord didn't respond properly after {} attempts", MAX_ATTEMPTS),
+      Self::Invalid(error) => write!(f, "the request was invalid: {}", error),
+    }
+  }
+}
+
+impl std::error::Error for MinionError {}
+
+impl From<MinionError> for TransportError {
+  fn from(error: MinionError) -> Self {
+    match error {
+      MinionError::Forbidden | MinionError::NotFound => Self::Unreachable,
+      MinionError::Unavailable | MinionError::Invalid(_) => Self::Failed,
+    }
   }
 }
 
+// Failures nobody can do anything about are only logged.
+fn log_failure<T>(result: Result<T, MinionError>, action: &str) {
+  if let Err(error) = result {
+    println!("couldn't {}: {}", action, error);
+  }
+}
+
+/// Makes requests to Discord. Clones share one client, and with it one view
+/// of Discord's rate limits. Failed requests can take seconds to retry, so
+/// the bot's event loop never waits on one.
+#[derive(Clone)]
 pub struct DiscordMinion {
-  client: HttpClient,
+  client: Arc<HttpClient>,
 }
 
 impl DiscordMinion {
-  fn new(token: String) -> Self {
+  fn new(client: HttpClient) -> Self {
     Self {
-      client: HttpClient::new(token),
+      client: Arc::new(client),
+    }
+  }
+
+  pub async fn send_message(&self, channel: Id<ChannelMarker>, content: String) -> Result<Message, MinionError> {
+    let response = self.request(|| self.client.create_message(channel).content(&content).map_err(MinionError::invalid)).await?;
+    response.model().await.map_err(MinionError::invalid)
+  }
+
+  pub async fn send_message_with_components(&self, channel: Id<ChannelMarker>, content: String, components: &[Component]) -> Result<Message, MinionError> {
+    let response = self.request(|| {
+      self.client.create_message(channel)
+        .content(&content).map_err(MinionError::invalid)?
+        .components(components).map_err(MinionError::invalid)
+    }).await?;
+    response.model().await.map_err(MinionError::invalid)
+  }
+
+  pub async fn send_embed(&self, channel: Id<ChannelMarker>, embed: Embed) -> Result<Message, MinionError> {
+    let embeds = [embed];
+    let response = self.request(|| self.client.create_message(channel).embeds(&embeds).map_err(MinionError::invalid)).await?;
+    response.model().await.map_err(MinionError::invalid)
+  }
+
+  /// Edits a message and replaces its buttons, or takes them away if there are none.
+  pub async fn edit_message_and_components(&self, channel: Id<ChannelMarker>, message: Id<MessageMarker>, new_content: String, components: &[Component]) -> Result<(), MinionError> {
+    self.request(|| {
+      self.client.update_message(channel, message)
+        .content(Some(&new_content)).map_err(MinionError::invalid)?
+        .components(Some(components)).map_err(MinionError::invalid)
+    }).await?;
+    Ok(())
+  }
+
+  pub async fn add_reaction(&self, channel: Id<ChannelMarker>, message: Id<MessageMarker>, reaction: CommonReactions) -> Result<(), MinionError> {
+    let emoji = reaction.val();
+    self.request(|| Ok(self.client.create_reaction(channel, message, &emoji))).await?;
+    Ok(())
+  }
+
+  /// Answers an interaction with a message only the user who sent it can see.
+  pub async fn respond_privately(&self, application_id: Id<ApplicationMarker>, interaction: Id<InteractionMarker>, token: &str, content: String) -> Result<(), MinionError> {
+    let response = InteractionResponse {
+      kind: InteractionResponseType::ChannelMessageWithSource,
+      data: Some(InteractionResponseDataBuilder::new().content(content).flags(MessageFlags::EPHEMERAL).build()),
+    };
+    let client = self.client.interaction(application_id);
+    self.request(|| Ok(client.create_response(interaction, token, &response))).await?;
+    Ok(())
+  }
+
+  /// Acknowledges an interaction without saying anything.
+  pub async fn respond_silently(&self, application_id: Id<ApplicationMarker>, interaction: Id<InteractionMarker>, token: &str) -> Result<(), MinionError> {
+    let response = InteractionResponse { kind: InteractionResponseType::DeferredUpdateMessage, data: None };
+    let client = self.client.interaction(application_id);
+    self.request(|| Ok(client.create_response(interaction, token, &response))).await?;
+    Ok(())
+  }
+
+  // Moderators are anyone who can manage messages in the server.
+  pub async fn is_moderator(&self, guild_id: Id<GuildMarker>, user: Id<UserMarker>, roles: &[Id<RoleMarker>]) -> Result<bool, MinionError> {
+    let guild = self.request(|| Ok(self.client.guild(guild_id))).await?.model().await.map_err(MinionError::invalid)?;
+    if guild.owner_id == user {
+      return Ok(true);
     }
+
+    let permissions = guild.roles.iter()
+      .filter(|role| role.id.cast() == guild.id || roles.contains(&role.id))
+      .fold(Permissions::empty(), |permissions, role| permissions | role.permissions);
+    Ok(permissions.intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_MESSAGES))
   }
 
-  pub async fn send_message(&self, channel: Id<ChannelMarker>, content: String) -> Message {
-    self.client.create_message(channel).content(&content).unwrap().await.unwrap().model().await.unwrap()
+  pub async fn delete_message(&self, channel: Id<ChannelMarker>, message: Id<MessageMarker>) -> Result<(), MinionError> {
+    self.request(|| Ok(self.client.delete_message(channel, message))).await?;
+    Ok(())
   }
 
-  pub async fn edit_message(&self, channel: Id<ChannelMarker>, message: Id<MessageMarker>, new_content: String) {
-    self.client.update_message(channel, message).content(Some(&new_content)).unwrap().await.unwrap();
+  pub async fn start_thread(&self, channel: Id<ChannelMarker>, message: Id<MessageMarker>, name: &str) -> Result<Id<ChannelMarker>, MinionError> {
+    let response = self.request(|| self.client.create_thread_from_message(channel, message, name).map_err(MinionError::invalid)).await?;
+    Ok(response.model().await.map_err(MinionError::invalid)?.id)
   }
 
-  pub async fn add_reaction(&self, message: &Message, reaction: CommonReactions) -> Response<EmptyBody> {
-    self.client.create_reaction(message.channel_id, message.id, &reaction.val()).await.unwrap()
+  /// Fails with `Forbidden` if the user doesn't take DMs from the bot.
+  pub async fn dm(&self, user: Id<UserMarker>, content: &str) -> Result<Message, MinionError> {
+    let dm_channel = self.request(|| Ok(self.client.create_private_channel(user))).await?.model().await.map_err(MinionError::invalid)?;
+    let response = self.request(|| self.client.create_message(dm_channel.id).content(content).map_err(MinionError::invalid)).await?;
+    response.model().await.map_err(MinionError::invalid)
   }
 
-  pub async fn dm_all(&self, users: &Vec<Id<UserMarker>>, content: String) {
-    for user_id in users {
-      let dm_channel = self.client.create_private_channel(*user_id).await.unwrap().model().await.unwrap();
+  // Sends the request `build` makes, making it again to retry failures that
+  // might not happen twice. Rate limits are waited out for as long as Discord
+  // asks.
+  async fn request<T, R>(&self, build: impl Fn() -> Result<R, MinionError>) -> Result<Response<T>, MinionError>
+  where
+    R: IntoFuture<Output = Result<Response<T>, HttpError>>,
+  {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+      let error = match build()?.await {
+        Ok(response) => return Ok(response),
+        Err(error) => error,
+      };
 
-      self.send_message(dm_channel.id, content.clone()).await;
+      let wait = match error.kind() {
+        ErrorType::Response { error: ApiError::Ratelimited(ratelimited), .. } => Duration::from_secs_f64(ratelimited.retry_after),
+        ErrorType::Response { status, .. } if status.get() == 403 => return Err(MinionError::Forbidden),
+        ErrorType::Response { status, .. } if status.get() == 404 => return Err(MinionError::NotFound),
+        ErrorType::Response { status, .. } if status.is_server_error() => delay,
+        ErrorType::Parsing { .. } | ErrorType::ServiceUnavailable { .. } | ErrorType::RequestError | ErrorType::RequestTimedOut => delay,
+        _ => return Err(MinionError::invalid(error)),
+      };
+      println!("Discord request failed on attempt {} of {}: {}", attempt, MAX_ATTEMPTS, error);
+      if attempt < MAX_ATTEMPTS {
+        sleep(wait).await;
+        delay *= 2;
+      }
     }
+
+    Err(MinionError::Unavailable)
   }
 }
 
@@ -156,18 +909,30 @@ enum CommonMessages {
   NoExistingGame,
   GameInProgress,
   NoPermission,
+  TooManyGames,
   AlreadyInGame,
+  NotInGame,
+  NotHost,
+  MentionNotInGame,
+  NoCancelPermission,
+  LastPlayerLeft,
 }
 
 impl CommonMessages {
   fn val(&self) -> String {
     let message = match *self {
       Self::NoDmCommands => "You cannot use commands in direct messages.",
-      Self::ExistingGame => "There is already a game in this server!",
-      Self::NoExistingGame => "There is no game in this server yet!",
+      Self::ExistingGame => "There is already a game in this channel!",
+      Self::NoExistingGame => "There is no game in this channel yet!",
       Self::GameInProgress => "This game is in progress, you cannot do that!",
-      Self::NoPermission => "Only the player who created the game may start it!",
-      Self::AlreadyInGame => "You may only join a game in one server at a time!",
+      Self::NoPermission => "Only the host may start the game!",
+      Self::TooManyGames => "This server already has as many games going as it can, wait for one to finish or join one!",
+      Self::AlreadyInGame => "You may only be in one game at a time!",
+      Self::NotInGame => "You aren't in this game!",
+      Self::NotHost => "Only the host may do that!",
+      Self::MentionNotInGame => "That player isn't in this game!",
+      Self::NoCancelPermission => "Only the host or a moderator may cancel the game!",
+      Self::LastPlayerLeft => "Everyone left, so the game was cancelled.",
     };
     message.to_string()
   }
@@ -178,16 +943,21 @@ pub enum CommonReactions {
   CheckmarkBlue,
   OctagonalSign,
   RedX,
+  Repeat,
 }
 
 impl CommonReactions {
-  fn val(&self) -> RequestReactionType {
-    let emoji = match *self {
+  fn emoji(&self) -> &'static str {
+    match *self {
       Self::CheckmarkGreen => "✅",
       Self::CheckmarkBlue => "☑️",
       Self::OctagonalSign => "🛑",
       Self::RedX => "❌",
-    };
-    RequestReactionType::Unicode { name: emoji }
+      Self::Repeat => "🔁",
+    }
+  }
+
+  fn val(&self) -> RequestReactionType<'_> {
+    RequestReactionType::Unicode { name: self.emoji() }
   }
 }
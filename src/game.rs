@@ -1,116 +1,572 @@
-use tokio::time::{sleep, Duration};
-use twilight_model::{id::{marker::{ChannelMarker, UserMarker, MessageMarker}, Id}, channel::Message};
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use tokio::{sync::Mutex, time::{sleep, Duration, Instant}};
 
-use crate::{round::{self, WordResult, Round}, discord::{CommonReactions, DiscordMinion}};
+use crate::{criteria::Criteron, round::{WordResult, Round, RoundSummary}, settings::{GameSettings, PlayMode}, transport::{GameTransport, PlayerId, Submission, TransportError, WordFeedback}, words::Dictionary};
 
-const NUM_ROUNDS: u32 = 3;
-const ROUND_DURATION: u64 = 15;
+/// How lobbies behave before the host starts the game.
+#[derive(Clone)]
+pub struct LobbySettings {
+  /// Lobbies nobody has joined, left or otherwise touched in this long are closed.
+  pub expire_after: Duration,
+  /// Start as soon as this many players are in the lobby.
+  pub start_at_players: Option<usize>,
+  /// Start this long after the lobby opens, whoever has joined by then.
+  pub start_after: Option<Duration>,
+}
+
+impl Default for LobbySettings {
+  fn default() -> Self {
+    Self {
+      expire_after: Duration::from_secs(10 * 60),
+      start_at_players: None,
+      start_after: None,
+    }
+  }
+}
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum GameState {
   Starting,
   BetweenRounds,
   ActivePlay,
+  Finished,
 }
 
-pub struct WordsAgainstStrangers {
-  public_channel: Id<ChannelMarker>,
+pub struct WordsAgainstStrangers<T: GameTransport> {
+  lobby_id: u64,
+  lobby: LobbySettings,
+  opened_at: SystemTime,
+  last_activity: Instant,
   state: GameState,
-  players: Vec<Id<UserMarker>>,
-  header_message: Option<Id<MessageMarker>>,
+  host: PlayerId,
+  players: Vec<PlayerId>,
+  // Why the game ended early, if it did.
+  cancelled: Option<String>,
+  // Players who send their words privately. Everyone else plays in the open,
+  // in `play_area`.
+  dm_players: Vec<PlayerId>,
+  play_area: Option<String>,
   rounds: Vec<Round>,
   round_index: i32,
-  minion: DiscordMinion,
+  transport: T,
+  dictionary: Arc<Dictionary>,
+  settings: GameSettings,
+  // Criteria for every round, in place of random ones.
+  criteria: Option<Vec<Criteron>>,
+  // Held while the header is being sent, so updates go out in order.
+  sending_header: Arc<Mutex<()>>,
 }
 
-impl WordsAgainstStrangers {
-  pub async fn new(public_channel: Id<ChannelMarker>, wordsmith: Id<UserMarker>, minion: DiscordMinion) -> Self {
-    let mut new_game = Self {
-      public_channel,
+impl<T: GameTransport> WordsAgainstStrangers<T> {
+  /// Opens a lobby. Nothing is shown until `update_header` is called.
+  pub fn new(
+    lobby_id: u64,
+    lobby: LobbySettings,
+    wordsmith: PlayerId,
+    transport: T,
+    dictionary: Arc<Dictionary>,
+    settings: GameSettings,
+  ) -> Self {
+    Self {
+      lobby_id,
+      lobby,
+      opened_at: SystemTime::now(),
+      last_activity: Instant::now(),
       state: GameState::Starting,
+      host: wordsmith,
       players: vec![wordsmith],
-      header_message: None,
+      cancelled: None,
+      dm_players: vec![],
+      play_area: None,
       rounds: vec![],
       round_index: 0,
-      minion,
-    };
-    let intro = new_game.minion.send_message(public_channel, new_game.make_intro()).await;
-    new_game.header_message = Some(intro.id);
+      transport,
+      dictionary,
+      settings,
+      criteria: None,
+      sending_header: Arc::new(Mutex::new(())),
+    }
+  }
 
-    new_game
+  /// Plays every round with `criteria`, so tests know which words score.
+  #[doc(hidden)]
+  pub fn with_criteria(mut self, criteria: Vec<Criteron>) -> Self {
+    self.criteria = Some(criteria);
+    self
   }
 
-  pub async fn start(&mut self) {
+  /// Stops the lobby taking new players or timers ahead of `run`.
+  pub fn close_lobby(&mut self) {
     self.state = GameState::BetweenRounds;
-    self.rounds = round::generate_rounds(&self.players, NUM_ROUNDS);
-    self.minion.send_message(self.public_channel, self.get_starting_message()).await;
-    self.minion.dm_all(self.get_players(), self.get_dm_opening()).await;
+  }
+
+  /// Brings the header up to date with the game. Updates are sent one at a
+  /// time, each built from the game as it is once its turn comes, so a slow
+  /// request can't leave an older header showing. The game is only locked
+  /// while the header is built.
+  pub async fn update_header(game: &Mutex<Self>) {
+    let sending_header = game.lock().await.sending_header.clone();
+    let _sending = sending_header.lock().await;
+    let (transport, content, open) = {
+      let game = game.lock().await;
+      let (content, open) = game.make_header();
+      (game.transport.clone(), content, open)
+    };
+    transport.set_header(content, open).await;
+  }
+
+  /// Plays the game through. The lock is only taken to move the game along
+  /// between requests to the transport, never across them or across a round,
+  /// so words keep being scored while the round runs.
+  pub async fn run(game: Arc<Mutex<Self>>) {
+    // Threads are started from the header, so it has to be up first.
+    Self::update_header(&game).await;
+    Self::open_play_area(&game).await;
+    let (transport, starting, opening, settings, dictionary, criteria) = {
+      let game = game.lock().await;
+      (
+        game.transport.clone(),
+        game.get_starting_message(),
+        game.get_opening(),
+        game.settings.clone(),
+        game.dictionary.clone(),
+        game.criteria.clone(),
+      )
+    };
+    transport.announce(starting).await;
+    Self::announce(&game, &transport, opening).await;
     sleep(Duration::from_millis(3000)).await;
 
-    for _ in 0..self.rounds.len() {
-      println!("Round {}", self.round_index);
-      self.state = GameState::ActivePlay;
-      self.minion.dm_all(self.get_players(), self.get_round_announcement()).await;
+    let mut scores: HashMap<PlayerId, u32> = game.lock().await.players.iter().map(|player| (*player, 0)).collect();
+    let mut summary = None;
+    for _ in 0..settings.rounds {
+      // Finding criteria with a fair number of answers can take a moment, so
+      // it happens before the lock is taken.
+      let round = match &criteria {
+        Some(criteria) => Round::with_criteria(scores, dictionary.clone(), &settings, criteria.clone()),
+        None => Round::new(scores, dictionary.clone(), &settings),
+      };
+      let announcement = game.lock().await.start_round(round);
+      Self::announce(&game, &transport, announcement).await;
 
-      sleep(Duration::from_secs(ROUND_DURATION)).await;
-      self.state = GameState::BetweenRounds;
-      self.round_index += 1;
-      // announce scores
-      // do it all again
+      sleep(settings.round_length).await;
+      let (round_summary, round_scores, round_number) = game.lock().await.end_round();
+      scores = round_scores;
+      transport.show_round_results(round_number, settings.rounds, &round_summary).await;
+      summary = Some(round_summary);
     }
 
-    // announce game end
-    // announce scores
+    game.lock().await.state = GameState::Finished;
+    if let Some(summary) = summary {
+      transport.show_final_results(&summary).await;
+    }
+    Self::update_header(&game).await;
   }
 
-  pub async fn add_player(&mut self, player: Id<UserMarker>) {
+  // Sets up wherever words will be played, without holding the lock while the
+  // transport does it.
+  async fn open_play_area(game: &Mutex<Self>) {
+    let (transport, play_mode) = {
+      let game = game.lock().await;
+      (game.transport.clone(), game.settings.play_mode)
+    };
+    let play_area = match play_mode {
+      PlayMode::Dms => None,
+      PlayMode::Channel => transport.open_play(false).await.ok(),
+      // We might not be allowed to start a thread, in which case the channel
+      // will do.
+      PlayMode::Thread => match transport.open_play(true).await {
+        Ok(thread) => Some(thread),
+        Err(_) => transport.open_play(false).await.ok(),
+      },
+    };
+
+    let mut game = game.lock().await;
+    // Failing all that, everyone plays by DM.
+    if play_area.is_none() {
+      game.dm_players = game.players.clone();
+    }
+    game.play_area = play_area;
+  }
+
+  fn start_round(&mut self, round: Round) -> String {
+    self.rounds.push(round);
+    self.state = GameState::ActivePlay;
+
+    self.get_round_announcement()
+  }
+
+  // Returns the round's summary, everyone's scores so far and which round it was.
+  fn end_round(&mut self) -> (RoundSummary, HashMap<PlayerId, u32>, u32) {
+    self.state = GameState::BetweenRounds;
+    let round_summary = self.get_current_round().end_round();
+    let scores = self.get_current_round().get_scores().clone();
+    self.round_index += 1;
+
+    (round_summary, scores, self.round_index as u32)
+  }
+
+  // Lobby changes only change the game; whoever makes them calls
+  // `update_header` once the game's unlocked.
+
+  pub fn add_player(&mut self, player: PlayerId) {
     self.players.push(player);
+    self.last_activity = Instant::now();
+  }
+
+  /// Takes a player out of the lobby, handing the game to the next player in
+  /// line if they were hosting. Returns false once nobody is left.
+  pub fn remove_player(&mut self, player: PlayerId) -> bool {
+    self.players.retain(|x| *x != player);
+    let Some(next_host) = self.players.first() else {
+      return false;
+    };
+
+    if self.host == player {
+      self.host = *next_host;
+    }
+    self.last_activity = Instant::now();
+    true
+  }
+
+  pub fn set_host(&mut self, player: PlayerId) {
+    self.host = player;
+    self.last_activity = Instant::now();
+  }
+
+  pub fn update_settings(&mut self, settings: GameSettings) {
+    self.settings = settings;
+    self.last_activity = Instant::now();
+  }
 
-    self.minion.edit_message(self.public_channel, self.header_message.unwrap(), self.make_intro()).await;
+  pub fn cancel(&mut self, reason: &str) {
+    self.state = GameState::Finished;
+    self.cancelled = Some(reason.to_string());
   }
 
-  pub async fn receive_word(&mut self, message: &Message, word: String) {
-    if self.state != GameState::ActivePlay { return };
+  /// Scores a word someone sent, if it's one this game should take. The
+  /// feedback is sent once the game is unlocked again.
+  pub fn receive_word(&mut self, submission: &Submission) -> Option<WordFeedback> {
+    if self.state != GameState::ActivePlay { return None };
+    // However it was typed, "Cart " is the word `cart`.
+    let word = submission.word.trim().to_lowercase();
+    let in_play = match submission.openly {
+      // Only single words count as guesses, so players can still chat.
+      true => self.play_area.is_some() && !word.contains(char::is_whitespace),
+      false => self.dm_players.contains(&submission.player),
+    };
+    if !in_play { return None };
+    let hide = submission.openly && self.settings.hidden_guesses;
 
-    let result = self.get_current_round().receive_word(message.author.id, word);
-    let reaction = match result {
-      WordResult::Invalid => CommonReactions::RedX,
-      WordResult::Blocked => CommonReactions::OctagonalSign,
-      WordResult::Scored => CommonReactions::CheckmarkGreen,
-      WordResult::ScoredBonus => CommonReactions::CheckmarkBlue,
+    let result = self.get_current_round().receive_word(submission.player, word.clone());
+    let explanation = match result {
+      WordResult::Blocked { limit } => {
+        // Hidden guesses shouldn't be given away by the explanation either.
+        let blocked = match hide {
+          true => format!("{} that word", self.transport.mention(submission.player)),
+          false => format!("`{}`", word),
+        };
+        Some(format!(
+          ":octagonal_sign: {} is blocked: {} already scored it this round, so nobody else can.",
+          blocked,
+          if limit == 1 { String::from("another player") } else { format!("{} other players", limit) },
+        ))
+      }
+      _ => None,
     };
-    self.minion.add_reaction(message, reaction).await;
+
+    Some(WordFeedback { result, hide, explanation })
+  }
+
+  // Sends to everyone playing privately and to the play area, if there is one.
+  // Players who can't be reached privately are moved over to open play, or
+  // dropped from the game if there's nowhere open to play.
+  async fn announce(game: &Mutex<Self>, transport: &T, content: String) {
+    let (dm_players, mut in_the_open) = {
+      let game = game.lock().await;
+      (game.dm_players.clone(), game.play_area.is_some())
+    };
+
+    let mut unreachable = vec![];
+    for player in dm_players {
+      match transport.message_player(player, content.clone()).await {
+        Ok(()) => {}
+        Err(TransportError::Unreachable) => unreachable.push(player),
+        // They'll hopefully get the next one.
+        Err(TransportError::Failed) => println!("couldn't message player {}", player),
+      }
+    }
+    if !unreachable.is_empty() {
+      if !in_the_open {
+        if let Ok(play_area) = transport.open_play(false).await {
+          game.lock().await.play_area = Some(play_area);
+          in_the_open = true;
+        }
+      }
+      {
+        let mut game = game.lock().await;
+        game.dm_players.retain(|player| !unreachable.contains(player));
+        if !in_the_open {
+          game.players.retain(|player| !unreachable.contains(player));
+        }
+      }
+
+      let mentions = unreachable.iter().map(|x| transport.mention(*x)).collect::<Vec<_>>().join(", ");
+      match in_the_open {
+        true => transport.announce_in_play(format!("{} I couldn't DM you, so send your words here instead!", mentions)).await,
+        false => transport.announce(format!("{} I couldn't DM you, so you've been dropped from the game.", mentions)).await,
+      }
+    }
+
+    if in_the_open {
+      transport.announce_in_play(content).await;
+    }
   }
 
   pub fn get_state(&self) -> GameState {
     self.state
   }
 
-  pub fn get_players(&self) -> &Vec<Id<UserMarker>> {
+  /// The round being played, or the next one between rounds, counting from 1.
+  pub fn get_round_number(&self) -> u32 {
+    self.round_index as u32 + 1
+  }
+
+  pub fn get_players(&self) -> &Vec<PlayerId> {
     &self.players
   }
 
+  pub fn get_lobby_id(&self) -> u64 {
+    self.lobby_id
+  }
+
+  pub fn get_lobby_settings(&self) -> &LobbySettings {
+    &self.lobby
+  }
+
+  /// When the lobby will expire if nothing else happens in it.
+  pub fn get_lobby_deadline(&self) -> Instant {
+    self.last_activity + self.lobby.expire_after
+  }
+
+  pub fn get_settings(&self) -> &GameSettings {
+    &self.settings
+  }
+
+  pub fn get_host(&self) -> PlayerId {
+    self.host
+  }
+
+  pub fn is_player(&self, player: PlayerId) -> bool {
+    self.players.contains(&player)
+  }
+
   fn get_current_round(&mut self) -> &mut Round {
     self.rounds.get_mut(self.round_index as usize).unwrap()
   }
 
+  // The header and whether it should still take players.
+  fn make_header(&self) -> (String, bool) {
+    match self.state {
+      GameState::Starting => (self.make_intro(), true),
+      GameState::BetweenRounds | GameState::ActivePlay => (self.make_intro(), false),
+      GameState::Finished => (self.make_outro(), false),
+    }
+  }
+
+  fn list_players(&self) -> String {
+    self.players.iter().map(|x| self.transport.mention(*x)).collect::<Vec<_>>().join(", ")
+  }
+
   fn make_intro(&self) -> String {
-    String::from("**Words Against Friends**\nPlayers: ") +
-      &self.players.iter().map(|x| format!("<@!{}>", x)).collect::<Vec<_>>().join(", ")
+    let mut auto_start = vec![];
+    if let Some(players) = self.lobby.start_at_players {
+      auto_start.push(format!("once {} players have joined", players));
+    }
+    if let Some(after) = self.lobby.start_after {
+      auto_start.push(self.transport.countdown(self.opened_at + after));
+    }
+    let auto_start = match auto_start.is_empty() {
+      true => String::new(),
+      false => format!("\nStarting automatically {}", auto_start.join(" or ")),
+    };
+
+    format!("**Words Against Friends**\nHost: {}\n{}{}\nPlayers: ", self.transport.mention(self.host), self.settings, auto_start) +
+      &self.list_players()
+  }
+
+  fn make_outro(&self) -> String {
+    if let Some(reason) = &self.cancelled {
+      return format!("**Words Against Friends**\n{} {}", reason, self.transport.new_game_hint());
+    }
+
+    format!("**Words Against Friends**\nThis game is over! {}\nPlayers: ", self.transport.new_game_hint()) +
+      &self.list_players()
   }
 
   fn get_starting_message(&self) -> String {
+    let where_to_play = match (self.settings.play_mode, &self.play_area) {
+      (PlayMode::Thread, Some(thread)) => format!("Head to {} to play!", thread),
+      (PlayMode::Channel, _) => String::from("Send your words right here once the round starts!"),
+      _ => String::from("Go to your DMs to get ready to play!"),
+    };
+
     String::from("**Words Against Friends**\nStarting now with players: ") +
-      &self.players.iter().map(|x| format!("<@!{}>", x)).collect::<Vec<_>>().join(", ") +
-      "\n:warning: Go to your DMs to get ready to play!"
+      &self.list_players() +
+      "\n:warning: " + &where_to_play
   }
 
-  fn get_dm_opening(&self) -> String {
+  fn get_opening(&self) -> String {
     String::from("**Words Against Friends**\nGet ready to play! Game starting soon...")
   }
 
   fn get_round_announcement(&self) -> String {
-    format!("**Words Against Friends: Round {} of {}**\nSend me words that: ", self.round_index+1, self.rounds.len()) +
+    format!("**Words Against Friends: Round {} of {}**\nSend me words that: ", self.round_index+1, self.settings.rounds) +
       &self.rounds.get(self.round_index as usize).unwrap().get_criteria_string()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+  /// Keeps everything a game sends as lines of text, for the test to read
+  /// back in order.
+  #[derive(Clone)]
+  struct MemoryTransport {
+    sent: UnboundedSender<String>,
+  }
+
+  impl GameTransport for MemoryTransport {
+    type Receipt = ();
+
+    fn mention(&self, player: PlayerId) -> String {
+      format!("player{}", player)
+    }
+
+    fn countdown(&self, _to: SystemTime) -> String {
+      String::from("soon")
+    }
+
+    fn new_game_hint(&self) -> String {
+      String::from("Play again!")
+    }
+
+    async fn set_header(&self, content: String, open: bool) {
+      self.sent.send(format!("header (open: {}): {}", open, content)).ok();
+    }
+
+    async fn announce(&self, content: String) {
+      self.sent.send(format!("announce: {}", content)).ok();
+    }
+
+    async fn open_play(&self, _separate: bool) -> Result<String, TransportError> {
+      Err(TransportError::Failed)
+    }
+
+    async fn announce_in_play(&self, content: String) {
+      self.sent.send(format!("in play: {}", content)).ok();
+    }
+
+    async fn message_player(&self, player: PlayerId, content: String) -> Result<(), TransportError> {
+      self.sent.send(format!("to {}: {}", self.mention(player), content)).ok();
+      Ok(())
+    }
+
+    async fn acknowledge(&self, _receipt: &(), _feedback: &WordFeedback) {}
+
+    async fn show_round_results(&self, round_number: u32, num_rounds: u32, summary: &RoundSummary) {
+      let standings = summary.players.iter()
+        .map(|player| format!("{} {} (+{})", self.mention(player.player), player.total, player.points))
+        .collect::<Vec<_>>();
+      self.sent.send(format!("round {} of {}: {}", round_number, num_rounds, standings.join(", "))).ok();
+    }
+
+    async fn show_final_results(&self, summary: &RoundSummary) {
+      let standings = summary.players.iter()
+        .map(|player| format!("{} {}", self.mention(player.player), player.total))
+        .collect::<Vec<_>>();
+      self.sent.send(format!("final: {}", standings.join(", "))).ok();
+    }
+  }
+
+  type TestGame = WordsAgainstStrangers<MemoryTransport>;
+
+  // Two players in a lobby for a game where every word starting with "ca" is
+  // worth a point, to whoever sends it first.
+  fn new_game(rounds: u32) -> (TestGame, UnboundedReceiver<String>) {
+    let dictionary = Arc::new(Dictionary::from_words(["cat", "car", "cart", "dog"].map(String::from)));
+    let options: Vec<(String, String)> = [("rounds", rounds.to_string().as_str()), ("block", "1"), ("long", "off"), ("rare", "off"), ("unique", "off"), ("hidden", "0")]
+      .iter()
+      .map(|(key, value)| (key.to_string(), value.to_string()))
+      .collect();
+    let mut settings = GameSettings::default();
+    settings.apply(&[], &options).unwrap();
+
+    let (sent, receiver) = mpsc::unbounded_channel();
+    let mut game = WordsAgainstStrangers::new(0, LobbySettings::default(), PlayerId(1), MemoryTransport { sent }, dictionary, settings)
+      .with_criteria(vec![Criteron::starting_with("ca")]);
+    game.add_player(PlayerId(2));
+    (game, receiver)
+  }
+
+  async fn play(game: &Mutex<TestGame>, player: u64, word: &str) -> Option<WordResult> {
+    let submission = Submission { player: PlayerId(player), word: word.to_string(), openly: false };
+    game.lock().await.receive_word(&submission).map(|feedback| feedback.result)
+  }
+
+  // Reads what the game sent until `line` turns up.
+  async fn wait_for(sent: &mut UnboundedReceiver<String>, line: &str) {
+    while let Some(next) = sent.recv().await {
+      if next.starts_with(line) {
+        return;
+      }
+    }
+    panic!("the game ended without sending {}", line);
+  }
+
+  // Time is paused, so the game's waits pass as soon as nothing else is going on.
+  #[tokio::test(start_paused = true)]
+  async fn plays_a_game_through() {
+    let (mut game, mut sent) = new_game(2);
+    let submission = Submission { player: PlayerId(1), word: String::from("cat"), openly: false };
+    assert!(game.receive_word(&submission).is_none(), "words shouldn't count before the game starts");
+    game.close_lobby();
+    let game = Arc::new(Mutex::new(game));
+    let finished = tokio::task::spawn(WordsAgainstStrangers::run(game.clone()));
+
+    wait_for(&mut sent, "to player2: **Words Against Friends: Round 1 of 2**").await;
+    assert_eq!(play(&game, 1, "cat").await, Some(WordResult::Scored));
+    assert_eq!(play(&game, 1, " Cat").await, Some(WordResult::AlreadyUsed));
+    assert_eq!(play(&game, 2, "CAT").await, Some(WordResult::Blocked { limit: 1 }));
+    assert_eq!(play(&game, 2, "cart").await, Some(WordResult::Scored));
+    assert_eq!(play(&game, 2, "car").await, Some(WordResult::Scored));
+    assert_eq!(play(&game, 2, "dog").await, Some(WordResult::Invalid));
+    // Only players' words count.
+    assert_eq!(play(&game, 3, "car").await, None);
+    wait_for(&mut sent, "round 1 of 2: player2 2 (+2), player1 1 (+1)").await;
+
+    wait_for(&mut sent, "to player1: **Words Against Friends: Round 2 of 2**").await;
+    assert_eq!(play(&game, 1, "car").await, Some(WordResult::Scored));
+    assert_eq!(play(&game, 1, "cart").await, Some(WordResult::Scored));
+    wait_for(&mut sent, "round 2 of 2: player1 3 (+2), player2 2 (+0)").await;
+
+    wait_for(&mut sent, "final: player1 3, player2 2").await;
+    wait_for(&mut sent, "header (open: false): **Words Against Friends**\nThis game is over! Play again!\nPlayers: player1, player2").await;
+    finished.await.unwrap();
+    assert!(game.lock().await.get_state() == GameState::Finished);
+  }
+
+  #[tokio::test]
+  async fn cancelling_says_why_in_the_header() {
+    let (game, mut sent) = new_game(1);
+    let game = Mutex::new(game);
+
+    WordsAgainstStrangers::update_header(&game).await;
+    wait_for(&mut sent, "header (open: true): **Words Against Friends**\nHost: player1").await;
+    game.lock().await.cancel("Nobody came.");
+    WordsAgainstStrangers::update_header(&game).await;
+    wait_for(&mut sent, "header (open: false): **Words Against Friends**\nNobody came. Play again!").await;
+  }
+}
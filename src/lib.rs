@@ -0,0 +1,13 @@
+pub mod command;
+pub mod criteria;
+pub mod discord;
+pub mod game;
+pub mod local;
+pub mod round;
+pub mod scoreboard;
+pub mod scoring;
+pub mod settings;
+pub mod simulate;
+pub mod slash;
+pub mod transport;
+pub mod words;
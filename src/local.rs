@@ -0,0 +1,220 @@
+use std::{io::BufRead, sync::Arc, thread, time::SystemTime};
+use tokio::{sync::{mpsc, Mutex}, time::{interval, Duration, Instant}};
+
+use crate::{
+  game::{GameState, LobbySettings, WordsAgainstStrangers},
+  round::{RoundSummary, WordResult},
+  scoreboard,
+  settings::{GameSettings, PlayMode},
+  transport::{GameTransport, PlayerId, Submission, TransportError, WordFeedback},
+  words::Dictionary,
+};
+
+/// Plays a game in the terminal, for playtesting without Discord. Arguments
+/// are player names and settings, e.g. `alice bob rounds=2 time=30`.
+pub async fn play(dictionary: Arc<Dictionary>, args: Vec<String>) -> anyhow::Result<()> {
+  let mut names = vec![];
+  let mut options = vec![];
+  for arg in args {
+    match arg.split_once('=') {
+      Some((key, value)) => options.push((key.to_lowercase(), value.to_string())),
+      None => names.push(arg),
+    }
+  }
+  if names.is_empty() {
+    names.push(String::from("player"));
+  }
+
+  let mut settings = GameSettings::default();
+  settings.apply(&[], &options).map_err(anyhow::Error::msg)?;
+  // Everyone shares the one terminal, so there's nothing to hide words from.
+  settings.play_mode = PlayMode::Channel;
+  settings.hidden_guesses = false;
+  let round_length = settings.round_length;
+
+  let players: Vec<PlayerId> = (0..names.len() as u64).map(PlayerId).collect();
+  let transport = LocalTransport { names: Arc::new(names) };
+  let mut game = WordsAgainstStrangers::new(0, LobbySettings::default(), players[0], transport.clone(), dictionary, settings);
+  for player in &players[1..] {
+    game.add_player(*player);
+  }
+  game.close_lobby();
+
+  let game = Arc::new(Mutex::new(game));
+  let mut finished = tokio::task::spawn(WordsAgainstStrangers::run(game.clone()));
+  let mut lines = read_lines();
+  let mut ticker = interval(Duration::from_secs(1));
+  // When the round that's on ends, and which round that is.
+  let mut round_ends: Option<(u32, Instant)> = None;
+  let mut last_warning = None;
+  let mut seat = players[0];
+  if players.len() > 1 {
+    println!("Playing as {}. Type @name to hand the keyboard to someone else.", transport.mention(seat));
+  }
+
+  loop {
+    tokio::select! {
+      _ = &mut finished => break,
+      Some(line) = lines.recv() => {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('@') {
+          match players.iter().find(|player| transport.mention(**player).eq_ignore_ascii_case(name)) {
+            Some(player) => {
+              seat = *player;
+              println!("Playing as {}.", transport.mention(seat));
+            }
+            None => println!("Nobody called {} is playing.", name),
+          }
+          continue;
+        }
+
+        for word in line.split_whitespace() {
+          let submission = Submission { player: seat, word: word.to_string(), openly: true };
+          let feedback = game.lock().await.receive_word(&submission);
+          if let Some(feedback) = feedback {
+            transport.acknowledge(&submission.word, &feedback).await;
+          }
+        }
+      }
+      _ = ticker.tick() => {
+        let (state, round) = {
+          let game = game.lock().await;
+          (game.get_state(), game.get_round_number())
+        };
+        if state != GameState::ActivePlay {
+          continue;
+        }
+
+        // Rounds follow each other too quickly for a tick to land between
+        // them, so a new round is spotted by its number.
+        let ends = match round_ends {
+          Some((ends_round, ends)) if ends_round == round => ends,
+          _ => {
+            let ends = Instant::now() + round_length;
+            round_ends = Some((round, ends));
+            last_warning = None;
+            // Ticking from here on lands each tick on a whole second left.
+            ticker.reset();
+            ends
+          }
+        };
+        let left = ends.saturating_duration_since(Instant::now()).as_secs_f64().ceil() as u64;
+        if (left.is_multiple_of(10) || left <= 3) && last_warning != Some(left) {
+          println!("⏳ {} seconds left", left);
+          last_warning = Some(left);
+        }
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// Stdin blocks, so it's read on its own thread.
+fn read_lines() -> mpsc::UnboundedReceiver<String> {
+  let (sender, receiver) = mpsc::unbounded_channel();
+  thread::spawn(move || {
+    for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+      if sender.send(line).is_err() {
+        break;
+      }
+    }
+  });
+  receiver
+}
+
+/// Plays a game on stdout. Players are known by their names, in the order
+/// they were given.
+#[derive(Clone)]
+struct LocalTransport {
+  names: Arc<Vec<String>>,
+}
+
+impl LocalTransport {
+  fn print(&self, content: &str) {
+    println!("\n{}", content.replace("**", "").replace('`', ""));
+  }
+}
+
+impl GameTransport for LocalTransport {
+  // The word that was sent.
+  type Receipt = String;
+
+  fn mention(&self, player: PlayerId) -> String {
+    self.names[player.0 as usize].clone()
+  }
+
+  fn countdown(&self, to: SystemTime) -> String {
+    let seconds = to.duration_since(SystemTime::now()).unwrap_or_default().as_secs();
+    format!("in {} seconds", seconds)
+  }
+
+  fn new_game_hint(&self) -> String {
+    String::from("Run with `--local` again to play another.")
+  }
+
+  async fn set_header(&self, content: String, open: bool) {
+    // The lobby is filled straight from the arguments, so only the finished
+    // header is worth showing.
+    if !open {
+      self.print(&content);
+    }
+  }
+
+  async fn announce(&self, content: String) {
+    self.print(&content);
+  }
+
+  async fn open_play(&self, _separate: bool) -> Result<String, TransportError> {
+    Ok(String::from("the terminal"))
+  }
+
+  async fn announce_in_play(&self, content: String) {
+    self.print(&content);
+  }
+
+  async fn message_player(&self, _player: PlayerId, content: String) -> Result<(), TransportError> {
+    self.print(&content);
+    Ok(())
+  }
+
+  async fn acknowledge(&self, word: &String, feedback: &WordFeedback) {
+    let verdict = match feedback.result {
+      WordResult::Invalid => "❌ doesn't count",
+      WordResult::Blocked { .. } => "🛑 blocked",
+      WordResult::AlreadyUsed => "🔁 already used",
+      WordResult::Scored => "✅ scored",
+      WordResult::ScoredBonus => "☑️ scored with a bonus",
+    };
+    println!("  {}: {}", word, verdict);
+    if let Some(explanation) = &feedback.explanation {
+      println!("  {}", explanation);
+    }
+  }
+
+  async fn show_round_results(&self, round_number: u32, num_rounds: u32, summary: &RoundSummary) {
+    let mut results = format!("Round {} of {} results", round_number, num_rounds);
+    if !summary.hidden_criteria.is_empty() {
+      results += &format!("\nHidden bonus for words that:\n- {}", summary.hidden_criteria.join("\n- "));
+    }
+    for (rank, player) in scoreboard::ranked(summary) {
+      let words = player.words.iter().map(|word| format!("{} ({})", word.word, word.total_points())).collect::<Vec<_>>();
+      results += &format!(
+        "\n#{} {}: {} points (+{} this round) {}",
+        rank,
+        self.mention(player.player),
+        player.total,
+        player.points,
+        words.join(", "),
+      );
+    }
+    self.print(&results);
+  }
+
+  async fn show_final_results(&self, summary: &RoundSummary) {
+    let standings = scoreboard::ranked(summary).into_iter()
+      .map(|(rank, player)| format!("#{} {}: {} points", rank, self.mention(player.player), player.total))
+      .collect::<Vec<_>>();
+    self.print(&format!("Final results\n{}", standings.join("\n")));
+  }
+}
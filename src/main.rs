@@ -1,35 +1,71 @@
 use futures::stream::StreamExt;
-use std::env;
+use std::{env, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
 use twilight_gateway::{Event, EventTypeFlags, Intents, Shard};
-
-mod criteria;
-mod discord;
-mod game;
-mod round;
-mod words;
+use twilight_http::Client as HttpClient;
+use words_against_friends::{discord, game, local, simulate, words};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-  words::load_words().unwrap();
+  let dictionary = Arc::new(words::Dictionary::load("words_alpha.txt", "parts_of_speech.txt", "common_words.txt")?);
+  if dictionary.is_empty() {
+    anyhow::bail!("words_alpha.txt has no words in it");
+  }
+  println!("Loaded {} words", dictionary.len());
+
+  // `--local` plays a game in the terminal instead of connecting to Discord,
+  // and `--simulate` plays lots of rounds with made-up players.
+  let mut args = env::args().skip(1);
+  match args.next().as_deref() {
+    Some("--local") => return local::play(dictionary, args.collect()).await,
+    Some("--simulate") => return simulate::run(dictionary, args.collect()),
+    _ => {}
+  }
 
   let prefix = String::from("w::");
   let token = env::var("DISCORD_TOKEN_WAF")?;
   let intents = Intents::GUILD_MESSAGES | Intents::DIRECT_MESSAGES | Intents::DIRECT_MESSAGE_REACTIONS | Intents::MESSAGE_CONTENT;
-  let event_types = EventTypeFlags::MESSAGE_CREATE;
+  let event_types = EventTypeFlags::MESSAGE_CREATE | EventTypeFlags::INTERACTION_CREATE;
 
   let (shard, mut events) = Shard::builder(token.clone(), intents).event_types(event_types).build();
   shard.start().await?;
 
-  let mut bot = discord::DiscordBot::new(prefix, token);
+  let (bot_events_sender, mut bot_events) = mpsc::unbounded_channel();
+  let max_games_per_guild = match env::var("WAF_GAMES_PER_SERVER") {
+    Ok(games) => games.parse()?,
+    Err(_) => 3,
+  };
+  let mut bot = discord::DiscordBot::new(prefix, HttpClient::new(token), dictionary, lobby_settings()?, max_games_per_guild, bot_events_sender);
+  bot.register_commands().await?;
 
-  while let Some(event) = events.next().await {
-    match event {
-      Event::MessageCreate(message) => {
-        bot.handle_message(message.0).await;
-      }
-      _ => {}
+  loop {
+    tokio::select! {
+      event = events.next() => match event {
+        Some(Event::MessageCreate(message)) => bot.handle_message(message.0).await,
+        Some(Event::InteractionCreate(interaction)) => bot.handle_interaction(interaction.0).await,
+        Some(_) => {}
+        None => break,
+      },
+      Some(event) = bot_events.recv() => bot.handle_bot_event(event).await,
     }
   }
 
   Ok(())
 }
+
+// Lobby timers can be tuned per deployment through the environment.
+fn lobby_settings() -> anyhow::Result<game::LobbySettings> {
+  let mut lobby = game::LobbySettings::default();
+
+  if let Ok(minutes) = env::var("WAF_LOBBY_TIMEOUT_MINUTES") {
+    lobby.expire_after = Duration::from_secs(minutes.parse::<u64>()? * 60);
+  }
+  if let Ok(players) = env::var("WAF_AUTO_START_PLAYERS") {
+    lobby.start_at_players = Some(players.parse()?);
+  }
+  if let Ok(seconds) = env::var("WAF_AUTO_START_SECONDS") {
+    lobby.start_after = Some(Duration::from_secs(seconds.parse()?));
+  }
+
+  Ok(lobby)
+}
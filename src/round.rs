@@ -1,100 +1,176 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use twilight_model::id::{marker::UserMarker, Id};
-
 use crate::criteria;
-use crate::words;
+use crate::scoring::{Bonus, BonusPolicy, ScoringMode};
+use crate::settings::GameSettings;
+use crate::transport::PlayerId;
+use crate::words::Dictionary;
 
 pub struct Round {
-  starting_scores: HashMap<Id<UserMarker>, u32>,
-  updated_scores: HashMap<Id<UserMarker>, u32>,
-  scored_words: HashMap<Id<UserMarker>, Vec<String>>,
-  use_count: HashMap<String, u32>,
+  starting_scores: HashMap<PlayerId, u32>,
+  updated_scores: HashMap<PlayerId, u32>,
+  scored_words: HashMap<PlayerId, Vec<String>>,
+  // Which players have scored each word.
+  use_count: HashMap<String, HashSet<PlayerId>>,
+  block_limit: Option<u32>,
   criteria: Vec<criteria::Criteron>,
-  num_players: u32,
+  hidden_criteria: Vec<criteria::Criteron>,
+  bonus_policy: BonusPolicy,
+  scoring_mode: ScoringMode,
+  dictionary: Arc<Dictionary>,
 }
 
 impl Round {
-  pub fn new(initial_scores: HashMap<Id<UserMarker>, u32>) -> Self {
-    let mut updated_scores: HashMap<Id<UserMarker>, u32> = HashMap::new();
-    let mut scored_words: HashMap<Id<UserMarker>, Vec<String>> = HashMap::new();
+  pub fn new(
+    initial_scores: HashMap<PlayerId, u32>,
+    dictionary: Arc<Dictionary>,
+    settings: &GameSettings,
+  ) -> Self {
+    let criteria = criteria::generate_random_criteria(&dictionary, &settings.difficulty.answer_bounds());
+    Self::with_criteria(initial_scores, dictionary, settings, criteria)
+  }
+
+  /// A round with the given criteria instead of random ones.
+  pub fn with_criteria(
+    initial_scores: HashMap<PlayerId, u32>,
+    dictionary: Arc<Dictionary>,
+    settings: &GameSettings,
+    criteria: Vec<criteria::Criteron>,
+  ) -> Self {
+    let mut scored_words: HashMap<PlayerId, Vec<String>> = HashMap::new();
     let num_players = initial_scores.len() as u32;
 
     for player_id in initial_scores.keys() {
-      updated_scores.insert(*player_id, *initial_scores.get(player_id).unwrap());
       scored_words.insert(*player_id, vec![]);
     }
 
     Self {
+      updated_scores: initial_scores.clone(),
       starting_scores: initial_scores,
-      updated_scores,
       scored_words,
       use_count: HashMap::new(),
-      criteria: criteria::generate_random_criteria(),
-      num_players,
+      block_limit: settings.blocking_rule.limit(num_players),
+      criteria,
+      hidden_criteria: criteria::generate_hidden_criteria(&dictionary, settings.bonus_policy.hidden_criteria),
+      bonus_policy: settings.bonus_policy.clone(),
+      scoring_mode: settings.scoring_mode.clone(),
+      dictionary,
     }
   }
 
+  pub fn get_criteria(&self) -> &[criteria::Criteron] {
+    &self.criteria
+  }
+
   pub fn get_criteria_string(&self) -> String {
-    self.criteria.iter().map(|criteron| format!("\n- {}", criteron.to_string())).collect::<Vec<_>>().join("")
+    self.criteria.iter().map(|criteron| format!("\n- {}", criteron)).collect::<Vec<_>>().join("")
   }
 
-  pub fn receive_word(&mut self, player: Id<UserMarker>, word: String) -> WordResult {
-    if !words::is_word(word.clone()) {
+  pub fn receive_word(&mut self, player: PlayerId, word: String) -> WordResult {
+    if !self.dictionary.contains(&word) {
       return WordResult::Invalid;
     }
 
-    if self.use_count.contains_key(&word) {
-      let count = self.use_count.get(&word).unwrap();
-      if count >= &(self.num_players / 2) {
-        return WordResult::Blocked;
-      } else {
-        self.use_count.insert(word.clone(), count+1);
-      }
-    } else {
-      self.use_count.insert(word.clone(), 1);
+    if self.scored_words.get(&player).unwrap().contains(&word) {
+      return WordResult::AlreadyUsed;
     }
 
     for criteron in &self.criteria {
-      if !criteron.test(word.clone()) {
+      if !criteron.test(&word, &self.dictionary) {
         return WordResult::Invalid;
       }
     }
 
+    let users = self.use_count.entry(word.clone()).or_default();
+    if let Some(limit) = self.block_limit {
+      if users.len() as u32 >= limit {
+        return WordResult::Blocked { limit };
+      }
+    }
+    users.insert(player);
+
     self.scored_words.get_mut(&player).unwrap().push(word.clone());
 
-    if words::deserves_bonus(word.clone()) {
+    if !self.bonus_policy.word_bonuses(&word, &self.dictionary, &self.hidden_criteria).is_empty() {
       WordResult::ScoredBonus
     } else {
       WordResult::Scored
     }
   }
 
-  pub fn end_round(&mut self) {
-    for player in self.scored_words.keys() {
-      let mut initial_score = *self.starting_scores.get(player).unwrap();
-      for word in self.scored_words.get(player).unwrap() {
-        initial_score += if words::deserves_bonus(word.clone()) { 2 } else { 1 };
-      }
+  pub fn end_round(&mut self) -> RoundSummary {
+    let mut players = vec![];
+
+    for (player, scored) in &self.scored_words {
+      let words: Vec<ScoredWord> = scored.iter().map(|word| {
+        let mut bonuses = self.bonus_policy.word_bonuses(word, &self.dictionary, &self.hidden_criteria);
+        if self.bonus_policy.unique_words && self.count_players_with(word) == 1 {
+          bonuses.push(Bonus::UniqueWord);
+        }
+
+        ScoredWord {
+          word: word.clone(),
+          points: self.scoring_mode.word_points(word),
+          bonus_points: bonuses.len() as u32 * self.scoring_mode.bonus_points(),
+          bonuses,
+        }
+      }).collect();
+      let points = words.iter().map(ScoredWord::total_points).sum();
+      let total = self.starting_scores.get(player).unwrap() + points;
+
+      self.updated_scores.insert(*player, total);
+      players.push(PlayerSummary { player: *player, words, points, total });
+    }
+
+    players.sort_by(|a, b| b.total.cmp(&a.total).then(b.points.cmp(&a.points)));
+    RoundSummary {
+      players,
+      hidden_criteria: self.hidden_criteria.iter().map(|criteron| criteron.to_string()).collect(),
     }
   }
-}
 
-pub enum WordResult { Invalid, Scored, ScoredBonus, Blocked }
+  fn count_players_with(&self, word: &str) -> usize {
+    self.scored_words.values().filter(|words| words.iter().any(|scored| scored == word)).count()
+  }
 
-pub fn generate_rounds(players: &Vec<Id<UserMarker>>, num_rounds: u32) -> Vec<Round> {
-  let mut rounds: Vec<Round> = vec![];
+  pub fn get_scores(&self) -> &HashMap<PlayerId, u32> {
+    &self.updated_scores
+  }
+}
 
-  for _ in 0..num_rounds {
-    let mut zero_scores: HashMap<Id<UserMarker>, u32> = HashMap::new();
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WordResult {
+  Invalid,
+  Scored,
+  ScoredBonus,
+  /// The word was already scored by `limit` other players this round.
+  Blocked { limit: u32 },
+  AlreadyUsed,
+}
 
-    for player in players {
-      zero_scores.insert(*player, 0);
-    }
+pub struct ScoredWord {
+  pub word: String,
+  pub points: u32,
+  pub bonuses: Vec<Bonus>,
+  pub bonus_points: u32,
+}
 
-    rounds.push(Round::new(zero_scores));
+impl ScoredWord {
+  pub fn total_points(&self) -> u32 {
+    self.points + self.bonus_points
   }
+}
+
+pub struct PlayerSummary {
+  pub player: PlayerId,
+  pub words: Vec<ScoredWord>,
+  pub points: u32,
+  pub total: u32,
+}
 
-  rounds
+/// The outcome of a finished round, best running total first.
+pub struct RoundSummary {
+  pub players: Vec<PlayerSummary>,
+  pub hidden_criteria: Vec<String>,
 }
@@ -0,0 +1,206 @@
+use twilight_model::channel::message::embed::Embed;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+
+use crate::round::{PlayerSummary, RoundSummary};
+
+const EMBED_COLOR: u32 = 0x3BA55C;
+// Discord caps embeds at 6000 characters in all, 4096 in the description, 25
+// fields and 1024 characters per field value. Lengths are counted in bytes,
+// which are never fewer.
+const MAX_EMBED_LENGTH: usize = 6000;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_FIELDS: usize = 25;
+const MAX_FIELD_LENGTH: usize = 1024;
+// Room for the "… and N more players" on the end of a list that had to be cut short.
+const MORE_LENGTH: usize = 32;
+// How much of the final results a tie can take up before the rest of the tied
+// players are cut.
+const MAX_HEADLINE_LENGTH: usize = 1024;
+
+pub fn round_results(round_number: u32, num_rounds: u32, summary: &RoundSummary) -> Embed {
+  let title = format!("Round {} of {} results", round_number, num_rounds);
+  let mut used = title.len();
+  let mut embed = EmbedBuilder::new().title(title).color(EMBED_COLOR);
+  let mut description = vec![];
+  if !summary.hidden_criteria.is_empty() {
+    description.push(format!("Hidden bonus for words that:\n- {}", summary.hidden_criteria.join("\n- ")));
+  }
+  // There's only room for a field per player for so many players.
+  if summary.players.len() > MAX_FIELDS {
+    description.push(format!("… and {} more players", summary.players.len() - MAX_FIELDS));
+  }
+  if !description.is_empty() {
+    let description = description.join("\n\n");
+    used += description.len();
+    embed = embed.description(description);
+  }
+
+  let fields: Vec<(String, String, &PlayerSummary)> = ranked(summary).into_iter()
+    .take(MAX_FIELDS)
+    .map(|(rank, player)| {
+      let name = format!("#{} · {} points (+{} this round)", rank, player.total, player.points);
+      (name, format!("<@{}>\n", player.player), player)
+    })
+    .collect();
+  // Whatever's left is shared out between everyone's words.
+  used += fields.iter().map(|(name, mention, _)| name.len() + mention.len()).sum::<usize>();
+  let share = MAX_EMBED_LENGTH.saturating_sub(used) / fields.len().max(1);
+
+  for (name, mention, player) in fields {
+    let words = words_list(player, share.min(MAX_FIELD_LENGTH - mention.len()));
+    embed = embed.field(EmbedFieldBuilder::new(name, mention + &words));
+  }
+
+  embed.build()
+}
+
+pub fn final_results(summary: &RoundSummary) -> Embed {
+  let ranked = ranked(summary);
+  let winners: Vec<&PlayerSummary> = ranked.iter()
+    .filter(|(rank, _)| *rank == 1)
+    .map(|(_, player)| *player)
+    .collect();
+
+  let headline = match winners.as_slice() {
+    [] => String::from("Nobody played!"),
+    [winner] => format!(":trophy: <@{}> wins with **{}** points!", winner.player, winner.total),
+    tied => format!(
+      ":trophy: It's a tie between {} with **{}** points each!",
+      fit(&tied.iter().map(|player| format!("<@{}>", player.player)).collect::<Vec<_>>(), ", ", MAX_HEADLINE_LENGTH, " players"),
+      tied[0].total,
+    ),
+  };
+  let standings: Vec<String> = ranked.iter()
+    .map(|(rank, player)| format!("**#{}** <@{}>: {} points", rank, player.player, player.total))
+    .collect();
+  let standings = fit(&standings, "\n", MAX_DESCRIPTION_LENGTH - headline.len() - 2, " players");
+
+  EmbedBuilder::new()
+    .title("Words Against Friends: Final results")
+    .color(EMBED_COLOR)
+    .description(format!("{}\n\n{}", headline, standings))
+    .build()
+}
+
+/// Pairs each player with their place by running total; tied players share a place.
+pub fn ranked(summary: &RoundSummary) -> Vec<(usize, &PlayerSummary)> {
+  let mut ranked: Vec<(usize, &PlayerSummary)> = vec![];
+
+  for (i, player) in summary.players.iter().enumerate() {
+    let rank = match ranked.last() {
+      Some((last_rank, last)) if last.total == player.total => *last_rank,
+      _ => i + 1,
+    };
+    ranked.push((rank, player));
+  }
+
+  ranked
+}
+
+fn words_list(player: &PlayerSummary, max_length: usize) -> String {
+  if player.words.is_empty() {
+    return String::from("*No words this round.*");
+  }
+
+  let entries: Vec<String> = player.words.iter().map(|word| {
+    // Spelling out "1" after every word is just noise in the default scoring mode.
+    let entry = match word.points {
+      1 => format!("`{}`", word.word),
+      points => format!("`{}` {}", word.word, points),
+    };
+    if word.bonuses.is_empty() {
+      return entry;
+    }
+    let bonuses = word.bonuses.iter().map(|bonus| bonus.to_string()).collect::<Vec<_>>().join(", ");
+    format!("**{}** *(+{}: {})*", entry, word.bonus_points, bonuses)
+  }).collect();
+
+  fit(&entries, ", ", max_length, "")
+}
+
+/// Joins as many entries as fit in `max_length`, then says how many more of
+/// `what` there were.
+fn fit(entries: &[String], separator: &str, max_length: usize, what: &str) -> String {
+  let mut list = String::new();
+  for (shown, entry) in entries.iter().enumerate() {
+    // Unless this is the last entry, there has to be room left to say how many
+    // more there were.
+    let separator_length = if list.is_empty() { 0 } else { separator.len() };
+    let more = if shown + 1 < entries.len() { MORE_LENGTH } else { 0 };
+    if list.len() + separator_length + entry.len() + more > max_length {
+      // No comma before the "… and N more", it isn't another entry.
+      if !list.is_empty() {
+        list += separator.trim_start_matches(',');
+      }
+      list += &format!("… and {} more{}", entries.len() - shown, what);
+      break;
+    }
+
+    if !list.is_empty() {
+      list += separator;
+    }
+    list += entry;
+  }
+
+  list
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{round::ScoredWord, transport::PlayerId};
+
+  fn embed_length(embed: &Embed) -> usize {
+    embed.title.as_ref().map_or(0, |title| title.chars().count()) +
+      embed.description.as_ref().map_or(0, |description| description.chars().count()) +
+      embed.fields.iter().map(|field| field.name.chars().count() + field.value.chars().count()).sum::<usize>()
+  }
+
+  #[test]
+  fn round_results_fit_in_an_embed() {
+    let players = (0..30).map(|i| PlayerSummary {
+      player: PlayerId(100_000_000_000_000_000 + i),
+      words: (0..300).map(|j| ScoredWord { word: format!("word{}", j), points: 3, bonuses: vec![], bonus_points: 0 }).collect(),
+      points: 900,
+      total: 900,
+    }).collect();
+    let summary = RoundSummary { players, hidden_criteria: vec![String::from("Contain `ab`")] };
+
+    let embed = round_results(1, 3, &summary);
+    assert_eq!(embed.fields.len(), MAX_FIELDS);
+    assert!(embed_length(&embed) <= MAX_EMBED_LENGTH, "embed is {} characters long", embed_length(&embed));
+    assert!(embed.fields.iter().all(|field| field.value.chars().count() <= MAX_FIELD_LENGTH));
+    assert!(embed.fields[0].value.ends_with(" more"));
+  }
+
+  #[test]
+  fn short_lists_are_shown_whole() {
+    let words = ["cat", "cart"].map(|word| ScoredWord { word: String::from(word), points: 1, bonuses: vec![], bonus_points: 0 });
+    let player = PlayerSummary { player: PlayerId(1), words: words.into(), points: 2, total: 2 };
+    let summary = RoundSummary { players: vec![player], hidden_criteria: vec![] };
+
+    assert_eq!(round_results(1, 1, &summary).fields[0].value, "<@1>\n`cat`, `cart`");
+  }
+
+  #[test]
+  fn crowds_fit_in_the_results() {
+    // Everyone tied, so the headline has to be cut short too.
+    let players: Vec<PlayerSummary> = (0..200).map(|i| PlayerSummary {
+      player: PlayerId(100_000_000_000_000_000 + i),
+      words: vec![],
+      points: 0,
+      total: 10,
+    }).collect();
+    let summary = RoundSummary { players, hidden_criteria: vec![] };
+
+    let embed = round_results(1, 3, &summary);
+    assert!(embed_length(&embed) <= MAX_EMBED_LENGTH, "embed is {} characters long", embed_length(&embed));
+    assert_eq!(embed.description.as_deref(), Some("… and 175 more players"));
+
+    let embed = final_results(&summary);
+    let description = embed.description.unwrap();
+    assert!(description.len() <= MAX_DESCRIPTION_LENGTH, "description is {} characters long", description.len());
+    assert!(description.contains(" more players with **10** points each!"));
+    assert!(description.ends_with(" more players"));
+  }
+}
@@ -0,0 +1,262 @@
+use std::{fmt, str::FromStr};
+
+use crate::{criteria::Criteron, words::Dictionary};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bonus { LongWord, RareWord, UniqueWord, HiddenCriteria }
+
+impl fmt::Display for Bonus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::LongWord => "long word",
+      Self::RareWord => "rare word",
+      Self::UniqueWord => "nobody else found it",
+      Self::HiddenCriteria => "hidden criteria",
+    })
+  }
+}
+
+/// Which bonuses a game hands out. Each bonus a word earns is worth the scoring
+/// mode's bonus value on top of the word's own score.
+#[derive(Clone)]
+pub struct BonusPolicy {
+  pub long_word_length: Option<usize>,
+  pub rare_words: bool,
+  pub unique_words: bool,
+  pub hidden_criteria: usize,
+}
+
+impl Default for BonusPolicy {
+  fn default() -> Self {
+    Self {
+      long_word_length: Some(10),
+      // The common list only covers a tenth of the dictionary, so nearly every
+      // word counts as rare. Until there are real word frequencies to go by,
+      // hosts have to ask for this.
+      rare_words: false,
+      unique_words: true,
+      hidden_criteria: 1,
+    }
+  }
+}
+
+impl BonusPolicy {
+  // Bonuses that can be judged from the word alone. Whether a word was unique
+  // is only known once the round is over.
+  pub fn word_bonuses(&self, word: &str, dictionary: &Dictionary, hidden_criteria: &[Criteron]) -> Vec<Bonus> {
+    let mut bonuses = vec![];
+
+    if self.long_word_length.is_some_and(|length| word.len() >= length) {
+      bonuses.push(Bonus::LongWord);
+    }
+    if self.rare_words && !dictionary.is_common(word) {
+      bonuses.push(Bonus::RareWord);
+    }
+    if !hidden_criteria.is_empty() && hidden_criteria.iter().all(|criteron| criteron.test(word, dictionary)) {
+      bonuses.push(Bonus::HiddenCriteria);
+    }
+
+    bonuses
+  }
+}
+
+/// How many players may score the same word in a round before it's blocked for
+/// everyone else.
+#[derive(Clone, Copy, Default)]
+pub enum BlockingRule {
+  Off,
+  After(u32),
+  /// Half the players, rounded up. Games of one or two players never block,
+  /// since a single shared word would already be half the table.
+  #[default]
+  Automatic,
+}
+
+impl BlockingRule {
+  pub fn limit(&self, num_players: u32) -> Option<u32> {
+    match self {
+      Self::Off => None,
+      Self::After(limit) => Some((*limit).max(1)),
+      Self::Automatic if num_players < 3 => None,
+      Self::Automatic => Some(num_players.div_ceil(2)),
+    }
+  }
+}
+
+impl fmt::Display for BlockingRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Off => f.write_str("off"),
+      Self::After(limit) => write!(f, "after {}", limit),
+      Self::Automatic => f.write_str("auto"),
+    }
+  }
+}
+
+impl FromStr for BlockingRule {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "off" => Ok(Self::Off),
+      "auto" => Ok(Self::Automatic),
+      _ => match value.parse::<u32>() {
+        Ok(limit) if limit > 0 => Ok(Self::After(limit)),
+        _ => Err(format!("`{}` isn't a blocking rule, use `off`, `auto` or a number of players.", value)),
+      },
+    }
+  }
+}
+
+// Standard English Scrabble tile values, a through z.
+const SCRABBLE_VALUES: [u32; 26] = [1, 3, 3, 2, 1, 4, 2, 4, 1, 8, 5, 1, 3, 1, 1, 3, 10, 1, 1, 1, 1, 4, 4, 8, 4, 10];
+
+#[derive(Clone)]
+pub struct TileTable {
+  values: [u32; 26],
+}
+
+impl Default for TileTable {
+  fn default() -> Self {
+    Self { values: SCRABBLE_VALUES }
+  }
+}
+
+impl TileTable {
+  pub fn with_value(mut self, letter: char, value: u32) -> Self {
+    if letter.is_ascii_lowercase() {
+      self.values[(letter as u8 - b'a') as usize] = value;
+    }
+    self
+  }
+
+  pub fn word_value(&self, word: &str) -> u32 {
+    word.bytes()
+      .filter(u8::is_ascii_lowercase)
+      .map(|letter| self.values[(letter - b'a') as usize])
+      .sum()
+  }
+}
+
+#[derive(Clone, Default)]
+pub enum ScoringMode {
+  /// Every accepted word is worth one point.
+  #[default]
+  Flat,
+  /// Words are worth the sum of their letters, multiplied by the largest
+  /// `(minimum length, multiplier)` pair the word is long enough for.
+  LetterValues { tiles: TileTable, length_multipliers: Vec<(usize, u32)> },
+}
+
+impl fmt::Display for ScoringMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Flat => "one point per word",
+      Self::LetterValues { .. } => "letter values",
+    })
+  }
+}
+
+impl ScoringMode {
+  pub fn letter_values() -> Self {
+    Self::LetterValues {
+      tiles: TileTable::default(),
+      length_multipliers: vec![(7, 2), (10, 3)],
+    }
+  }
+
+  pub fn from_name(name: &str) -> Result<Self, String> {
+    match name {
+      "flat" => Ok(Self::Flat),
+      "scrabble" | "letters" => Ok(Self::letter_values()),
+      _ => Err(format!("Unknown scoring mode `{}`, try `flat` or `scrabble`.", name)),
+    }
+  }
+
+  /// Overrides one tile's value, as given by an option like `q=5`.
+  pub fn set_tile(&mut self, letter: &str, value: &str) -> Result<(), String> {
+    let Self::LetterValues { tiles, .. } = self else {
+      return Err(String::from("Letter values can only be changed when scoring with `scrabble`."));
+    };
+
+    let parsed = letter.parse::<char>().ok()
+      .filter(char::is_ascii_lowercase)
+      .zip(value.parse::<u32>().ok());
+    let Some((letter, value)) = parsed else {
+      return Err(format!("`{}={}` isn't a letter value, write them like `q=5`.", letter, value));
+    };
+    *tiles = std::mem::take(tiles).with_value(letter, value);
+
+    Ok(())
+  }
+
+  pub fn word_points(&self, word: &str) -> u32 {
+    match self {
+      Self::Flat => 1,
+      Self::LetterValues { tiles, length_multipliers } => {
+        let multiplier = length_multipliers.iter()
+          .filter(|(length, _)| word.len() >= *length)
+          .map(|(_, multiplier)| *multiplier)
+          .max()
+          .unwrap_or(1);
+        tiles.word_value(word) * multiplier
+      }
+    }
+  }
+
+  pub fn bonus_points(&self) -> u32 {
+    match self {
+      Self::Flat => 1,
+      Self::LetterValues { .. } => 5,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn words_are_worth_their_letters_times_the_largest_multiplier() {
+    assert_eq!(ScoringMode::Flat.word_points("strawberry"), 1);
+
+    let scoring = ScoringMode::letter_values();
+    assert_eq!(scoring.word_points("cat"), 5);
+    assert_eq!(scoring.word_points("catalog"), 10 * 2);
+    // Long enough for both multipliers, but only the larger counts.
+    assert_eq!(scoring.word_points("strawberry"), 18 * 3);
+
+    let scoring = ScoringMode::LetterValues { tiles: TileTable::default(), length_multipliers: vec![(10, 3), (7, 2)] };
+    assert_eq!(scoring.word_points("strawberry"), 18 * 3);
+  }
+
+  #[test]
+  fn tiles_can_be_overridden() {
+    let mut scoring = ScoringMode::letter_values();
+    assert_eq!(scoring.word_points("quiz"), 22);
+    assert!(scoring.set_tile("q", "5").is_ok());
+    assert!(scoring.set_tile("z", "0").is_ok());
+    assert_eq!(scoring.word_points("quiz"), 7);
+
+    assert!(scoring.set_tile("Q", "5").is_err());
+    assert!(scoring.set_tile("qu", "5").is_err());
+    assert!(scoring.set_tile("q", "lots").is_err());
+    assert_eq!(scoring.word_points("quiz"), 7);
+
+    assert!(ScoringMode::Flat.set_tile("q", "5").is_err());
+  }
+
+  #[test]
+  fn blocking_limits_depend_on_the_table() {
+    assert_eq!(BlockingRule::Off.limit(10), None);
+    // Blocking at a table of one or two would take words off everyone.
+    assert_eq!(BlockingRule::Automatic.limit(1), None);
+    assert_eq!(BlockingRule::Automatic.limit(2), None);
+    assert_eq!(BlockingRule::Automatic.limit(3), Some(2));
+    assert_eq!(BlockingRule::Automatic.limit(4), Some(2));
+    assert_eq!(BlockingRule::Automatic.limit(5), Some(3));
+    assert_eq!(BlockingRule::After(2).limit(10), Some(2));
+    // Someone has to be able to score a word.
+    assert_eq!(BlockingRule::After(0).limit(10), Some(1));
+  }
+}
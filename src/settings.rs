@@ -0,0 +1,207 @@
+use std::{fmt, time::Duration};
+
+use crate::{criteria::AnswerBounds, scoring::{BlockingRule, BonusPolicy, ScoringMode}};
+
+pub const MAX_ROUNDS: u32 = 10;
+pub const MIN_ROUND_SECONDS: u64 = 10;
+pub const MAX_ROUND_SECONDS: u64 = 300;
+pub const MAX_HIDDEN_CRITERIA: usize = 3;
+
+/// How hard a round's criteria are, by how many dictionary words they admit.
+#[derive(Clone, Copy, Default)]
+pub enum Difficulty {
+  Easy,
+  #[default]
+  Normal,
+  Hard,
+}
+
+impl Difficulty {
+  pub fn answer_bounds(&self) -> AnswerBounds {
+    match self {
+      Self::Easy => AnswerBounds { min: 200, max: 20_000 },
+      Self::Normal => AnswerBounds { min: 20, max: 10_000 },
+      Self::Hard => AnswerBounds { min: 5, max: 500 },
+    }
+  }
+}
+
+impl fmt::Display for Difficulty {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Easy => "easy",
+      Self::Normal => "normal",
+      Self::Hard => "hard",
+    })
+  }
+}
+
+/// Where players send their words.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PlayMode {
+  #[default]
+  Dms,
+  /// A thread started from the lobby message.
+  Thread,
+  /// The channel the game was opened in.
+  Channel,
+}
+
+impl fmt::Display for PlayMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Dms => "in DMs",
+      Self::Thread => "in a thread",
+      Self::Channel => "in this channel",
+    })
+  }
+}
+
+/// Everything the host can change about a game before it starts.
+#[derive(Clone)]
+pub struct GameSettings {
+  pub rounds: u32,
+  pub round_length: Duration,
+  pub scoring_mode: ScoringMode,
+  pub difficulty: Difficulty,
+  pub blocking_rule: BlockingRule,
+  pub bonus_policy: BonusPolicy,
+  pub play_mode: PlayMode,
+  /// Delete words sent in a thread or channel once they're scored, so other
+  /// players can't copy them.
+  pub hidden_guesses: bool,
+}
+
+impl Default for GameSettings {
+  fn default() -> Self {
+    Self {
+      rounds: 3,
+      round_length: Duration::from_secs(15),
+      scoring_mode: ScoringMode::default(),
+      difficulty: Difficulty::default(),
+      blocking_rule: BlockingRule::default(),
+      bonus_policy: BonusPolicy::default(),
+      play_mode: PlayMode::default(),
+      hidden_guesses: true,
+    }
+  }
+}
+
+impl GameSettings {
+  /// Applies a command's arguments, like `scrabble` and `rounds=5 q=5`.
+  /// Nothing changes unless every one of them is valid.
+  pub fn apply(&mut self, args: &[String], options: &[(String, String)]) -> Result<(), String> {
+    let mut settings = self.clone();
+    let mut tiles = vec![];
+
+    // A bare word picks the scoring mode, as in `w::new scrabble`.
+    for arg in args {
+      settings.scoring_mode = ScoringMode::from_name(arg)?;
+    }
+
+    for (key, value) in options {
+      let key = key.as_str();
+      let value = value.as_str();
+      match key {
+        "rounds" => settings.rounds = parse_within(key, value, 1, MAX_ROUNDS)?,
+        "time" => settings.round_length = Duration::from_secs(parse_within(key, value, MIN_ROUND_SECONDS, MAX_ROUND_SECONDS)?),
+        "scoring" => settings.scoring_mode = ScoringMode::from_name(value)?,
+        "difficulty" => settings.difficulty = match value {
+          "easy" => Difficulty::Easy,
+          "normal" => Difficulty::Normal,
+          "hard" => Difficulty::Hard,
+          _ => return Err(format!("`{}` isn't a difficulty, use `easy`, `normal` or `hard`.", value)),
+        },
+        "block" => settings.blocking_rule = value.parse()?,
+        "long" => settings.bonus_policy.long_word_length = match value {
+          "off" => None,
+          _ => Some(parse_within(key, value, 4, 20)?),
+        },
+        "rare" => settings.bonus_policy.rare_words = parse_switch(key, value)?,
+        "unique" => settings.bonus_policy.unique_words = parse_switch(key, value)?,
+        "hidden" => settings.bonus_policy.hidden_criteria = parse_within(key, value, 0, MAX_HIDDEN_CRITERIA)?,
+        "play" => settings.play_mode = match value {
+          "dm" | "dms" => PlayMode::Dms,
+          "thread" => PlayMode::Thread,
+          "channel" => PlayMode::Channel,
+          _ => return Err(format!("`{}` isn't somewhere to play, use `dm`, `thread` or `channel`.", value)),
+        },
+        "guesses" => settings.hidden_guesses = match value {
+          "hidden" => true,
+          "visible" => false,
+          _ => return Err(String::from("`guesses` can only be `hidden` or `visible`.")),
+        },
+        // Single letters set tile values, once the scoring mode is known.
+        _ if key.len() == 1 => tiles.push((key, value)),
+        _ => return Err(format!(
+          "Unknown setting `{}`. You can set `rounds`, `time`, `scoring`, `difficulty`, `block`, `long`, `rare`, `unique`, `hidden`, `play` and `guesses`.",
+          key,
+        )),
+      }
+    }
+
+    for (letter, value) in tiles {
+      settings.scoring_mode.set_tile(letter, value)?;
+    }
+
+    *self = settings;
+    Ok(())
+  }
+}
+
+impl fmt::Display for GameSettings {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let policy = &self.bonus_policy;
+    let mut bonuses = vec![];
+    if let Some(length) = policy.long_word_length {
+      bonuses.push(format!("{}+ letters", length));
+    }
+    if policy.rare_words {
+      bonuses.push(String::from("rare words"));
+    }
+    if policy.unique_words {
+      bonuses.push(String::from("unique words"));
+    }
+    if policy.hidden_criteria > 0 {
+      bonuses.push(format!("{} hidden criteria", policy.hidden_criteria));
+    }
+    let bonuses = match bonuses.is_empty() {
+      true => String::from("none"),
+      false => bonuses.join(", "),
+    };
+
+    let guesses = match (self.play_mode, self.hidden_guesses) {
+      (PlayMode::Dms, _) => "",
+      (_, true) => ", guesses hidden",
+      (_, false) => ", guesses visible",
+    };
+
+    write!(
+      f,
+      "Rounds: {} of {}s\nScoring: {}\nDifficulty: {}\nBlocking: {}\nBonuses: {}\nPlaying: {}{}",
+      self.rounds,
+      self.round_length.as_secs(),
+      self.scoring_mode,
+      self.difficulty,
+      self.blocking_rule,
+      bonuses,
+      self.play_mode,
+      guesses,
+    )
+  }
+}
+
+fn parse_within<T: PartialOrd + fmt::Display + std::str::FromStr>(key: &str, value: &str, min: T, max: T) -> Result<T, String> {
+  match value.parse::<T>() {
+    Ok(parsed) if parsed >= min && parsed <= max => Ok(parsed),
+    _ => Err(format!("`{}` must be a number from {} to {}.", key, min, max)),
+  }
+}
+
+fn parse_switch(key: &str, value: &str) -> Result<bool, String> {
+  match value {
+    "on" => Ok(true),
+    "off" => Ok(false),
+    _ => Err(format!("`{}` can only be `on` or `off`.", key)),
+  }
+}
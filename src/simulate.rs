@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Arc};
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+use crate::{
+  criteria::{self, CriteronType},
+  round::{Round, WordResult},
+  settings::GameSettings,
+  transport::PlayerId,
+  words::Dictionary,
+};
+
+// The fastest anyone can be expected to think of and type a word.
+const SECONDS_PER_WORD: f64 = 2.0;
+// How much less likely a player is to think of a word outside the common list.
+// Everyone reaches for the same common words first, which is what gets words
+// blocked.
+const RARE_WORD_FACTOR: f64 = 0.02;
+// How much less likely a word is to come to mind for each extra letter.
+const LETTER_FACTOR: f64 = 0.5;
+
+/// What happened in every simulated round that used one type of criteron.
+#[derive(Default)]
+struct TypeStats {
+  answer_counts: Vec<usize>,
+  out_of_bounds: usize,
+  submissions: usize,
+  blocked: usize,
+  // Points between the best and worst player in each round.
+  spreads: Vec<u32>,
+}
+
+impl TypeStats {
+  fn add(&mut self, answers: usize, out_of_bounds: bool, submissions: usize, blocked: usize, spread: u32) {
+    self.answer_counts.push(answers);
+    self.out_of_bounds += out_of_bounds as usize;
+    self.submissions += submissions;
+    self.blocked += blocked;
+    self.spreads.push(spread);
+  }
+
+  fn report(&mut self, name: &str) {
+    let rounds = self.answer_counts.len();
+    self.answer_counts.sort_unstable();
+    self.spreads.sort_unstable();
+    println!(
+      "{:<14}{:>7}{:>8}{:>8}{:>8}{:>8}{:>8}{:>9.1}%{:>9.1}%{:>8}{:>8}",
+      name,
+      rounds,
+      self.answer_counts[0],
+      percentile(&self.answer_counts, 10),
+      percentile(&self.answer_counts, 50),
+      percentile(&self.answer_counts, 90),
+      self.answer_counts[rounds - 1],
+      100.0 * self.out_of_bounds as f64 / rounds as f64,
+      100.0 * self.blocked as f64 / self.submissions.max(1) as f64,
+      percentile(&self.spreads, 50),
+      percentile(&self.spreads, 90),
+    );
+  }
+}
+
+/// Plays thousands of rounds with made-up players and reports how hard the
+/// generated criteria turned out to be. Arguments are `rounds=N`, player
+/// skills as `skills=0.1,0.3,0.6` and any game settings, e.g. `difficulty=hard`.
+///
+/// A player's skill is how much of the fastest pace, a word every two seconds,
+/// they keep up.
+pub fn run(dictionary: Arc<Dictionary>, args: Vec<String>) -> anyhow::Result<()> {
+  let mut num_rounds = 2000;
+  let mut skills = vec![0.1, 0.3, 0.6];
+  let mut options = vec![];
+  for arg in args {
+    let Some((key, value)) = arg.split_once('=') else {
+      anyhow::bail!("Arguments look like `rounds=2000`, `{}` doesn't", arg);
+    };
+    match key {
+      "rounds" => num_rounds = value.parse()?,
+      "skills" => skills = value.split(',').map(str::parse).collect::<Result<_, _>>()?,
+      _ => options.push((key.to_lowercase(), value.to_string())),
+    }
+  }
+  if num_rounds == 0 || skills.is_empty() || skills.iter().any(|skill| !(0.0..=1.0).contains(skill)) {
+    anyhow::bail!("Simulate at least one round, with skills from 0 to 1");
+  }
+
+  let mut settings = GameSettings::default();
+  settings.apply(&[], &options).map_err(anyhow::Error::msg)?;
+  let bounds = settings.difficulty.answer_bounds();
+  let round_seconds = settings.round_length.as_secs_f64();
+  let players: Vec<PlayerId> = (0..skills.len() as u64).map(PlayerId).collect();
+
+  let mut rng = thread_rng();
+  let mut by_type: HashMap<CriteronType, TypeStats> = HashMap::new();
+  let mut overall = TypeStats::default();
+  let mut points_by_player: HashMap<PlayerId, u64> = HashMap::new();
+
+  for _ in 0..num_rounds {
+    let scores = players.iter().map(|player| (*player, 0)).collect();
+    let mut round = Round::new(scores, dictionary.clone(), &settings);
+    let types: Vec<CriteronType> = round.get_criteria().iter().map(|criteron| criteron.get_type()).collect();
+    let answers: Vec<String> = criteria::answers(round.get_criteria(), &dictionary).into_iter().map(String::from).collect();
+
+    // Everyone plays at once, so each word is sent at some moment in the round
+    // and whoever gets a shared word in first scores it.
+    let mut attempts: Vec<(f64, PlayerId, String)> = vec![];
+    for (player, skill) in players.iter().zip(&skills) {
+      let seconds_per_word = SECONDS_PER_WORD / skill;
+      let num_words = ((round_seconds / seconds_per_word).ceil() as usize).min(answers.len());
+      let weight = |word: &String| {
+        let commonness = if dictionary.is_common(word) { 1.0 } else { RARE_WORD_FACTOR };
+        commonness * LETTER_FACTOR.powi(word.len() as i32)
+      };
+      let Ok(words) = answers.choose_multiple_weighted(&mut rng, num_words, weight) else { continue };
+      for (i, word) in words.enumerate() {
+        // The last word might not be in before time's up.
+        let sent_at = (i as f64 + rng.gen::<f64>()) * seconds_per_word;
+        if sent_at < round_seconds {
+          attempts.push((sent_at, *player, word.clone()));
+        }
+      }
+    }
+    attempts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let submissions = attempts.len();
+    let mut blocked = 0;
+    for (_, player, word) in attempts {
+      if let WordResult::Blocked { .. } = round.receive_word(player, word) {
+        blocked += 1;
+      }
+    }
+
+    let summary = round.end_round();
+    for player in &summary.players {
+      *points_by_player.entry(player.player).or_default() += player.points as u64;
+    }
+    let most = summary.players.iter().map(|player| player.points).max().unwrap_or(0);
+    let least = summary.players.iter().map(|player| player.points).min().unwrap_or(0);
+
+    let out_of_bounds = answers.len() < bounds.min || answers.len() > bounds.max;
+    for criteron_type in types {
+      by_type.entry(criteron_type).or_default().add(answers.len(), out_of_bounds, submissions, blocked, most - least);
+    }
+    overall.add(answers.len(), out_of_bounds, submissions, blocked, most - least);
+  }
+
+  println!("{} rounds at {} difficulty, aiming for {} to {} answers\n", num_rounds, settings.difficulty, bounds.min, bounds.max);
+  println!(
+    "{:<14}{:>7}{:>8}{:>8}{:>8}{:>8}{:>8}{:>10}{:>10}{:>8}{:>8}",
+    "criteron", "rounds", "min", "p10", "median", "p90", "max", "outside", "blocked", "spread", "p90",
+  );
+  let mut by_type: Vec<(CriteronType, TypeStats)> = by_type.into_iter().collect();
+  by_type.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.answer_counts.len()));
+  for (criteron_type, stats) in &mut by_type {
+    stats.report(&format!("{:?}", criteron_type));
+  }
+  overall.report("all");
+
+  println!("\nAverage points per round by skill:");
+  for (player, skill) in players.iter().zip(&skills) {
+    let points = points_by_player.get(player).copied().unwrap_or(0);
+    println!("{:>6}: {:.1}", skill, points as f64 / num_rounds as f64);
+  }
+
+  Ok(())
+}
+
+fn percentile<T: Copy>(sorted: &[T], percent: usize) -> T {
+  sorted[(sorted.len() - 1) * percent / 100]
+}
@@ -0,0 +1,110 @@
+use twilight_model::{
+  application::{
+    command::{Command as ApplicationCommand, CommandType},
+    interaction::{application_command::{CommandData, CommandOptionValue}, message_component::MessageComponentInteractionData},
+  },
+  channel::message::component::{ActionRow, Button, ButtonStyle, Component},
+};
+use twilight_util::builder::command::{BooleanBuilder, CommandBuilder, IntegerBuilder, StringBuilder, SubCommandBuilder};
+
+use crate::{command::{self, Command}, settings};
+
+pub const COMMAND_NAME: &str = "waf";
+
+// Subcommands and the prefix commands they stand in for.
+const SUBCOMMANDS: [(&str, &str); 4] = [("new", "new"), ("join", "join"), ("start", "start"), ("settings", "set")];
+
+// Buttons on the lobby header: custom ID, label, style and the command they run.
+const LOBBY_BUTTONS: [(&str, &str, ButtonStyle, &str); 4] = [
+  ("waf:join", "Join", ButtonStyle::Success, "join"),
+  ("waf:leave", "Leave", ButtonStyle::Secondary, "leave"),
+  ("waf:start", "Start", ButtonStyle::Primary, "start"),
+  ("waf:settings", "Settings", ButtonStyle::Secondary, "set"),
+];
+
+/// The `/waf` command, registered with Discord on startup.
+pub fn commands() -> Vec<ApplicationCommand> {
+  let new = SubCommandBuilder::new("new", "Open a lobby in this channel");
+  let settings = SubCommandBuilder::new("settings", "Show the lobby's settings, or change them if you're the host");
+
+  vec![
+    CommandBuilder::new(COMMAND_NAME, "Play Words Against Friends", CommandType::ChatInput)
+      .dm_permission(false)
+      .option(settings_options(new))
+      .option(SubCommandBuilder::new("join", "Join the lobby"))
+      .option(SubCommandBuilder::new("start", "Start the game"))
+      .option(settings_options(settings))
+      .build(),
+  ]
+}
+
+fn settings_options(subcommand: SubCommandBuilder) -> SubCommandBuilder {
+  subcommand
+    .option(IntegerBuilder::new("rounds", "How many rounds to play").min_value(1).max_value(settings::MAX_ROUNDS.into()))
+    .option(IntegerBuilder::new("time", "Seconds per round")
+      .min_value(settings::MIN_ROUND_SECONDS as i64)
+      .max_value(settings::MAX_ROUND_SECONDS as i64))
+    .option(StringBuilder::new("scoring", "How words are scored").choices([("One point per word", "flat"), ("Letter values", "scrabble")]))
+    .option(StringBuilder::new("difficulty", "How many answers each round has").choices([("Easy", "easy"), ("Normal", "normal"), ("Hard", "hard")]))
+    .option(StringBuilder::new("block", "Players who may score a word before it's blocked: off, auto or a number"))
+    .option(StringBuilder::new("long", "Letters a word needs for the long word bonus, or off"))
+    .option(BooleanBuilder::new("rare", "Give a bonus for rare words"))
+    .option(BooleanBuilder::new("unique", "Give a bonus for words nobody else found"))
+    .option(IntegerBuilder::new("hidden", "How many hidden criteria to reward").min_value(0).max_value(settings::MAX_HIDDEN_CRITERIA as i64))
+    .option(StringBuilder::new("play", "Where players send their words").choices([("DMs", "dm"), ("A thread", "thread"), ("This channel", "channel")]))
+    .option(StringBuilder::new("guesses", "Whether words sent in a thread or channel stay up").choices([("Hidden", "hidden"), ("Visible", "visible")]))
+}
+
+/// Turns a `/waf` interaction into the same command its prefix form parses to.
+pub fn parse(data: &CommandData) -> Option<Command> {
+  if data.name != COMMAND_NAME {
+    return None;
+  }
+  let subcommand = data.options.first()?;
+  let CommandOptionValue::SubCommand(options) = &subcommand.value else {
+    return None;
+  };
+  let (_, name) = SUBCOMMANDS.iter().find(|(subcommand_name, _)| *subcommand_name == subcommand.name)?;
+
+  let options = options.iter().filter_map(|option| {
+    let value = match &option.value {
+      CommandOptionValue::Integer(value) => value.to_string(),
+      CommandOptionValue::String(value) => value.clone(),
+      CommandOptionValue::Boolean(value) => String::from(if *value { "on" } else { "off" }),
+      _ => return None,
+    };
+    Some((option.name.clone(), value))
+  }).collect();
+
+  Some(Command {
+    spec: command::find(name)?,
+    args: vec![],
+    options,
+    mentions: vec![],
+  })
+}
+
+pub fn lobby_buttons() -> Vec<Component> {
+  let buttons = LOBBY_BUTTONS.iter().map(|(custom_id, label, style, _)| Component::Button(Button {
+    custom_id: Some(custom_id.to_string()),
+    disabled: false,
+    emoji: None,
+    label: Some(label.to_string()),
+    style: *style,
+    url: None,
+  }));
+
+  vec![Component::ActionRow(ActionRow { components: buttons.collect() })]
+}
+
+/// Turns a click on one of the lobby buttons into the command it stands for.
+pub fn parse_button(data: &MessageComponentInteractionData) -> Option<Command> {
+  let (_, _, _, name) = LOBBY_BUTTONS.iter().find(|(custom_id, ..)| *custom_id == data.custom_id)?;
+
+  Some(Command {
+    spec: command::find(name)?,
+    args: vec![],
+    options: vec![],
+    mentions: vec![],
+  })
+}
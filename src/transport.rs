@@ -0,0 +1,77 @@
+use std::{fmt, future::Future, time::SystemTime};
+
+use crate::round::{RoundSummary, WordResult};
+
+/// A player, whichever platform they're playing on.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PlayerId(pub u64);
+
+impl fmt::Display for PlayerId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+/// A word a player sent in.
+pub struct Submission {
+  pub player: PlayerId,
+  pub word: String,
+  /// Sent where everyone can see it, rather than privately.
+  pub openly: bool,
+}
+
+/// How the game judged a submission, for the transport to pass on.
+pub struct WordFeedback {
+  pub result: WordResult,
+  /// Take the word down so other players can't copy it.
+  pub hide: bool,
+  pub explanation: Option<String>,
+}
+
+/// Why a transport couldn't reach a player or open somewhere to play.
+#[derive(Debug)]
+pub enum TransportError {
+  /// Trying again won't help, e.g. a player's DMs are closed.
+  Unreachable,
+  /// It might work next time.
+  Failed,
+}
+
+/// Everything a game needs to say to its players. Each game gets its own
+/// transport, tied to wherever the game was opened. Failures the game can't
+/// work around are the transport's to deal with; the rest are returned.
+pub trait GameTransport: Clone + Send + Sync + 'static {
+  /// Identifies a submission so it can be answered, e.g. the message it came in.
+  type Receipt: Send + Sync;
+
+  /// How to refer to a player in messages.
+  fn mention(&self, player: PlayerId) -> String;
+
+  /// How to refer to a moment that's coming up, e.g. when a lobby auto-starts.
+  fn countdown(&self, to: SystemTime) -> String;
+
+  /// Tells players how to start another game, once theirs is over.
+  fn new_game_hint(&self) -> String;
+
+  /// Posts the lobby header, or updates it once it's been posted. Players can
+  /// join from it while `open` is true.
+  fn set_header(&self, content: String, open: bool) -> impl Future<Output = ()> + Send;
+
+  /// Posts where the game was opened.
+  fn announce(&self, content: String) -> impl Future<Output = ()> + Send;
+
+  /// Makes somewhere players can send words in the open, apart from the rest
+  /// of the channel if `separate` is set. Returns how to refer to it.
+  fn open_play(&self, separate: bool) -> impl Future<Output = Result<String, TransportError>> + Send;
+
+  /// Posts wherever words are being sent in the open.
+  fn announce_in_play(&self, content: String) -> impl Future<Output = ()> + Send;
+
+  fn message_player(&self, player: PlayerId, content: String) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+  fn acknowledge(&self, receipt: &Self::Receipt, feedback: &WordFeedback) -> impl Future<Output = ()> + Send;
+
+  fn show_round_results(&self, round_number: u32, num_rounds: u32, summary: &RoundSummary) -> impl Future<Output = ()> + Send;
+
+  fn show_final_results(&self, summary: &RoundSummary) -> impl Future<Output = ()> + Send;
+}
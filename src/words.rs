@@ -1,64 +1,344 @@
-use std::{process::Command, fs::File, io::{BufReader, BufRead, self}};
-use ttaw;
-
-static mut WORDS: Vec<String> = vec![];
+use std::{collections::{HashMap, HashSet}, fmt, fs::File, io::{self, BufRead, BufReader}, path::Path, sync::OnceLock};
+use rand::{seq::SliceRandom, Rng};
 
 #[derive(PartialEq, Clone)]
 pub enum PartsOfSpeech { Noun, Verb, Adverb, Adjective }
 
-impl PartsOfSpeech {
-  pub fn to_string(&self) -> String {
-    String::from(match self {
+impl fmt::Display for PartsOfSpeech {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
       Self::Noun => "noun",
       Self::Verb => "verb",
       Self::Adverb => "adverb",
       Self::Adjective => "adjective",
     })
   }
+}
 
-  pub fn wordpos_opt(&self) -> &str {
+impl PartsOfSpeech {
+  pub const ALL: [PartsOfSpeech; 4] = [Self::Noun, Self::Verb, Self::Adverb, Self::Adjective];
+
+  // The single-letter tags used in `parts_of_speech.txt`.
+  fn from_tag(tag: char) -> Option<Self> {
+    match tag {
+      'n' => Some(Self::Noun),
+      'v' => Some(Self::Verb),
+      'r' => Some(Self::Adverb),
+      'a' => Some(Self::Adjective),
+      _ => None,
+    }
+  }
+
+  fn bit(&self) -> u8 {
     match self {
-      Self::Noun => "-n",
-      Self::Verb => "-v",
-      Self::Adverb => "-r",
-      Self::Adjective => "-a",
+      Self::Noun => 1,
+      Self::Verb => 1 << 1,
+      Self::Adverb => 1 << 2,
+      Self::Adjective => 1 << 3,
     }
   }
 }
 
-pub fn load_words() -> io::Result<()> {
-  let file = File::open("words_alpha.txt")?;
-  let reader = BufReader::new(file);
+#[derive(Clone, Copy)]
+pub enum NgramPosition { Start, End, Anywhere }
 
-  for line in reader.lines() {
-    unsafe { WORDS.push(line?); }
+const NGRAM_LENGTHS: [usize; 2] = [2, 3];
+
+/// The word list every game checks guesses against. Loaded once at startup and
+/// shared between games behind an `Arc`.
+pub struct Dictionary {
+  index: HashSet<String>,
+  sorted: Vec<String>,
+  // Indices into `sorted`, ordered by the reversed spelling of each word so
+  // suffix lookups can binary search the same way prefix lookups do.
+  by_suffix: Vec<usize>,
+  parts_of_speech: HashMap<String, u8>,
+  common: HashSet<String>,
+  // Built on first use: encoding every word phonetically takes a moment.
+  rhymes: OnceLock<HashMap<String, Vec<usize>>>,
+  ngrams: [OnceLock<Vec<(String, usize)>>; 3],
+}
+
+impl Dictionary {
+  pub fn load(
+    words_path: impl AsRef<Path>,
+    parts_of_speech_path: impl AsRef<Path>,
+    common_words_path: impl AsRef<Path>,
+  ) -> io::Result<Self> {
+    let mut dictionary = Self::from_words(read_list(words_path)?);
+    dictionary.parts_of_speech = load_parts_of_speech(parts_of_speech_path)?;
+    dictionary.common = read_list(common_words_path)?.into_iter().collect();
+
+    Ok(dictionary)
+  }
+
+  pub fn from_words(words: impl IntoIterator<Item = String>) -> Self {
+    let mut sorted: Vec<String> = words.into_iter()
+      .map(|word| word.trim().to_lowercase())
+      .filter(|word| !word.is_empty())
+      .collect();
+    sorted.sort();
+    sorted.dedup();
+
+    let mut by_suffix: Vec<usize> = (0..sorted.len()).collect();
+    by_suffix.sort_by(|a, b| sorted[*a].bytes().rev().cmp(sorted[*b].bytes().rev()));
+
+    Self {
+      index: sorted.iter().cloned().collect(),
+      sorted,
+      by_suffix,
+      parts_of_speech: HashMap::new(),
+      common: HashSet::new(),
+      rhymes: OnceLock::new(),
+      ngrams: Default::default(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.sorted.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sorted.is_empty()
   }
 
-  Ok(())
+  pub fn contains(&self, word: &str) -> bool {
+    self.index.contains(word)
+  }
+
+  pub fn is_part_of_speech(&self, word: &str, pos: &PartsOfSpeech) -> bool {
+    self.parts_of_speech.get(word).is_some_and(|tags| tags & pos.bit() != 0)
+  }
+
+  pub fn is_common(&self, word: &str) -> bool {
+    self.common.contains(word)
+  }
+
+  pub fn random_word(&self, rng: &mut impl Rng) -> Option<&str> {
+    self.sorted.choose(rng).map(String::as_str)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &str> {
+    self.sorted.iter().map(String::as_str)
+  }
+
+  pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    let start = self.sorted.partition_point(|word| word.as_str() < prefix);
+
+    self.sorted[start..].iter()
+      .take_while(move |word| word.starts_with(prefix))
+      .map(String::as_str)
+  }
+
+  pub fn with_suffix<'a>(&'a self, suffix: &'a str) -> impl Iterator<Item = &'a str> {
+    let start = self.by_suffix.partition_point(|i| self.sorted[*i].bytes().rev().lt(suffix.bytes().rev()));
+
+    self.by_suffix[start..].iter()
+      .map(|i| self.sorted[*i].as_str())
+      .take_while(move |word| word.ends_with(suffix))
+  }
+
+  pub fn containing<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a str> {
+    self.iter().filter(move |word| word.contains(pattern))
+  }
+
+  pub fn rhyming_with(&self, word: &str) -> Vec<&str> {
+    let rhymes = self.rhymes.get_or_init(|| {
+      let mut rhymes: HashMap<String, Vec<usize>> = HashMap::new();
+      for (i, word) in self.sorted.iter().enumerate() {
+        for key in rhyme_keys(word) {
+          rhymes.entry(key).or_default().push(i);
+        }
+      }
+      rhymes
+    });
+
+    let mut indices: Vec<usize> = rhyme_keys(word).iter()
+      .filter_map(|key| rhymes.get(key))
+      .flatten()
+      .copied()
+      .collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    indices.into_iter().map(|i| self.sorted[i].as_str()).collect()
+  }
+
+  /// Every bigram and trigram found at `position`, paired with the number of
+  /// words it appears in there, most common first.
+  pub fn ngrams(&self, position: NgramPosition) -> &[(String, usize)] {
+    self.ngrams[position as usize].get_or_init(|| {
+      let mut counts: HashMap<&str, usize> = HashMap::new();
+
+      for word in &self.sorted {
+        let mut found: Vec<&str> = vec![];
+        for n in NGRAM_LENGTHS.into_iter().filter(|n| *n <= word.len()) {
+          match position {
+            NgramPosition::Start => found.push(&word[..n]),
+            NgramPosition::End => found.push(&word[word.len() - n..]),
+            NgramPosition::Anywhere => {
+              for start in 0..=word.len() - n {
+                let ngram = &word[start..start + n];
+                if !found.contains(&ngram) {
+                  found.push(ngram);
+                }
+              }
+            }
+          }
+        }
+
+        for ngram in found {
+          *counts.entry(ngram).or_default() += 1;
+        }
+      }
+
+      let mut ngrams: Vec<(String, usize)> = counts.into_iter()
+        .map(|(ngram, count)| (ngram.to_string(), count))
+        .collect();
+      ngrams.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+      ngrams
+    })
+  }
 }
 
-pub fn is_word(word: String) -> bool {
-  unsafe { WORDS.contains(&word) }
+// Two words rhyme when their phonetic encodings match after the first sound,
+// which is the same comparison `ttaw::metaphone::rhyme` makes pairwise.
+fn rhyme_keys(word: &str) -> Vec<String> {
+  if word.trim().is_empty() {
+    return vec![];
+  }
+
+  let encoding = ttaw::metaphone::encoding(word);
+  let mut keys = vec![
+    encoding.primary.get(1..).unwrap_or_default().to_string(),
+    encoding.secondary.get(1..).unwrap_or_default().to_string(),
+  ];
+  keys.dedup();
+  keys
 }
 
-pub fn deserves_bonus(word: String) -> bool {
-  false // TODO
+pub fn is_rhyme(first: &str, second: &str) -> bool {
+  ttaw::metaphone::rhyme(first, second)
 }
 
-pub fn is_rhyme(first: String, second: String) -> bool {
-  ttaw::metaphone::rhyme(&first, &second)
+fn read_list(path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+  let reader = BufReader::new(File::open(path)?);
+  let mut words = vec![];
+
+  for line in reader.lines() {
+    let line = line?;
+    if !line.starts_with('#') {
+      words.push(line);
+    }
+  }
+
+  Ok(words)
 }
 
-pub fn is_part_of_speech(word: String, pos: &PartsOfSpeech) -> bool {
-  let command = Command::new("cmd").args(&["/C", &format!("wordpos get -c {} {}", pos.wordpos_opt(), &word)]).output().unwrap();
-  let output = String::from_utf8(command.stdout).unwrap();
-  let counts = output.lines().nth(1).unwrap().split_whitespace();
-  let mut total = 0;
-  for count in counts {
-    if count.contains("1") {
-      total += 1;
+fn load_parts_of_speech(path: impl AsRef<Path>) -> io::Result<HashMap<String, u8>> {
+  let reader = BufReader::new(File::open(path)?);
+  let mut parts_of_speech = HashMap::new();
+
+  for (number, line) in reader.lines().enumerate() {
+    let line = line?;
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad part of speech entry on line {}: {:?}", number + 1, line));
+    let (word, tags) = line.split_once(' ').ok_or_else(invalid)?;
+    let mut bits = 0;
+    for tag in tags.chars() {
+      bits |= PartsOfSpeech::from_tag(tag).ok_or_else(invalid)?.bit();
     }
+
+    parts_of_speech.insert(word.to_string(), bits);
+  }
+
+  Ok(parts_of_speech)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn dictionary() -> Dictionary {
+    let words = ["a", "art", "at", "banana", "bat", "cart", "cat", "catalog", "chat", "dog", "scat", "start", "tart"];
+    Dictionary::from_words(words.map(String::from))
+  }
+
+  fn sorted<'a>(words: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut words: Vec<&str> = words.into_iter().collect();
+    words.sort();
+    words
+  }
+
+  fn count(ngrams: &[(String, usize)], ngram: &str) -> Option<usize> {
+    ngrams.iter().find(|(found, _)| found == ngram).map(|(_, count)| *count)
+  }
+
+  #[test]
+  fn finds_words_by_suffix() {
+    let dictionary = dictionary();
+
+    // Spread all over the alphabetical list, but next to each other reversed.
+    assert_eq!(sorted(dictionary.with_suffix("at")), ["at", "bat", "cat", "chat", "scat"]);
+    assert_eq!(sorted(dictionary.with_suffix("art")), ["art", "cart", "start", "tart"]);
+    // A whole word is its own suffix, and words shorter than the suffix are skipped.
+    assert_eq!(sorted(dictionary.with_suffix("tart")), ["start", "tart"]);
+    assert_eq!(sorted(dictionary.with_suffix("scat")), ["scat"]);
+    assert_eq!(sorted(dictionary.with_suffix("log")), ["catalog"]);
+    assert!(dictionary.with_suffix("xyz").next().is_none());
+    assert!(dictionary.with_suffix("bobcat").next().is_none());
+  }
+
+  #[test]
+  fn finds_words_by_prefix() {
+    let dictionary = dictionary();
+
+    assert_eq!(dictionary.with_prefix("ca").collect::<Vec<_>>(), ["cart", "cat", "catalog"]);
+    assert_eq!(dictionary.with_prefix("cat").collect::<Vec<_>>(), ["cat", "catalog"]);
+    assert_eq!(dictionary.with_prefix("a").collect::<Vec<_>>(), ["a", "art", "at"]);
+    assert!(dictionary.with_prefix("catalogue").next().is_none());
+    assert!(dictionary.with_prefix("z").next().is_none());
   }
 
-  total == 2
+  #[test]
+  fn finds_words_containing_a_pattern() {
+    let dictionary = dictionary();
+
+    assert_eq!(dictionary.containing("ta").collect::<Vec<_>>(), ["catalog", "start", "tart"]);
+    assert_eq!(dictionary.containing("nan").collect::<Vec<_>>(), ["banana"]);
+    assert!(dictionary.containing("xyz").next().is_none());
+  }
+
+  #[test]
+  fn finds_rhymes() {
+    let dictionary = dictionary();
+
+    let rhymes = dictionary.rhyming_with("hat");
+    assert!(rhymes.contains(&"bat"));
+    assert!(rhymes.contains(&"cat"));
+    assert!(!rhymes.contains(&"dog"));
+    assert!(dictionary.rhyming_with("").is_empty());
+  }
+
+  #[test]
+  fn counts_ngrams_by_position() {
+    let dictionary = dictionary();
+
+    let start = dictionary.ngrams(NgramPosition::Start);
+    assert_eq!(start[..3], [(String::from("ca"), 3), (String::from("ba"), 2), (String::from("cat"), 2)]);
+    // Too short to have any.
+    assert_eq!(count(start, "a"), None);
+
+    let end = dictionary.ngrams(NgramPosition::End);
+    assert_eq!(end[..3], [(String::from("at"), 5), (String::from("art"), 4), (String::from("rt"), 4)]);
+
+    // Counted once per word, however many times it turns up in it.
+    let anywhere = dictionary.ngrams(NgramPosition::Anywhere);
+    assert_eq!(count(anywhere, "an"), Some(1));
+    assert_eq!(count(anywhere, "ana"), Some(1));
+    assert_eq!(count(anywhere, "at"), Some(6));
+  }
 }
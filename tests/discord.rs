@@ -0,0 +1,411 @@
+//! Drives `DiscordBot` through whole games against a stand-in for Discord's
+//! HTTP API, checking what it sends back.
+
+use std::{
+  convert::Infallible,
+  net::SocketAddr,
+  sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, Mutex},
+  time::Duration,
+};
+
+use hyper::{service::{make_service_fn, service_fn}, Body, Response, Server};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use twilight_http::Client as HttpClient;
+use twilight_model::channel::Message;
+use words_against_friends::{criteria::Criteron, discord::{BotEvent, DiscordBot}, game::LobbySettings, words::Dictionary};
+
+const GUILD: u64 = 100;
+const CHANNEL: u64 = 200;
+const BOT: u64 = 300;
+const ALICE: u64 = 401;
+const BOB: u64 = 402;
+
+// Long enough for a whole game: a 3 second opening and a 10 second round.
+const PATIENCE: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+struct Request {
+  method: String,
+  path: String,
+  body: Value,
+}
+
+impl Request {
+  fn content(&self) -> &str {
+    self.body["content"].as_str().unwrap_or("")
+  }
+
+  fn embed_title(&self) -> &str {
+    self.body["embeds"][0]["title"].as_str().unwrap_or("")
+  }
+}
+
+/// Answers every request the bot makes with something plausible, and keeps a
+/// record of them all.
+struct FakeDiscord {
+  address: SocketAddr,
+  state: Arc<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+  requests: Mutex<Vec<Request>>,
+  next_id: AtomicU64,
+  // Users who don't accept DMs from the bot.
+  closed_dms: Mutex<Vec<u64>>,
+  // How many of the next requests fail with a server error.
+  outage: AtomicUsize,
+}
+
+impl FakeDiscord {
+  fn start() -> Self {
+    let state = Arc::new(FakeState { next_id: AtomicU64::new(10_000), ..Default::default() });
+
+    let shared = state.clone();
+    let make_service = make_service_fn(move |_| {
+      let state = shared.clone();
+      async move {
+        Ok::<_, Infallible>(service_fn(move |request| respond(request, state.clone())))
+      }
+    });
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+
+    Self { address, state }
+  }
+
+  fn client(&self) -> HttpClient {
+    HttpClient::builder()
+      .token(String::from("test-token"))
+      .proxy(self.address.to_string(), true)
+      .ratelimiter(None)
+      .build()
+  }
+
+  fn requests(&self) -> Vec<Request> {
+    self.state.requests.lock().unwrap().clone()
+  }
+
+  /// Waits for the bot to make a request matching `predicate`.
+  async fn wait_for(&self, what: &str, predicate: impl Fn(&Request) -> bool) -> Request {
+    self.wait_until(what, |requests| requests.iter().any(&predicate)).await;
+    self.requests().into_iter().find(predicate).unwrap()
+  }
+
+  /// Waits until the requests the bot has made so far satisfy `condition`.
+  async fn wait_until(&self, what: &str, condition: impl Fn(&[Request]) -> bool) {
+    let deadline = tokio::time::Instant::now() + PATIENCE;
+    while tokio::time::Instant::now() < deadline {
+      if condition(&self.requests()) {
+        return;
+      }
+      tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("never saw {}, only:\n{:#?}", what, self.requests());
+  }
+}
+
+async fn respond(request: hyper::Request<Body>, state: Arc<FakeState>) -> Result<Response<Body>, Infallible> {
+  let method = request.method().to_string();
+  let path = request.uri().path().trim_start_matches("/api/v10").to_string();
+  let bytes = hyper::body::to_bytes(request.into_body()).await.unwrap();
+  let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+  state.requests.lock().unwrap().push(Request { method: method.clone(), path: path.clone(), body: body.clone() });
+
+  if state.outage.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+    return Ok(Response::builder().status(500).body(Body::empty()).unwrap());
+  }
+
+  let id = state.next_id.fetch_add(1, Ordering::SeqCst);
+  let segments: Vec<&str> = path.split('/').skip(1).collect();
+  let reply = match (method.as_str(), segments.as_slice()) {
+    ("POST", ["users", "@me", "channels"]) => {
+      let recipient: u64 = body["recipient_id"].as_str().unwrap().parse().unwrap();
+      json!({ "id": dm_channel(recipient).to_string(), "type": 1 })
+    }
+    ("POST", ["channels", channel, "messages"]) => {
+      let channel = channel.parse().unwrap();
+      if state.closed_dms.lock().unwrap().iter().any(|user| dm_channel(*user) == channel) {
+        let refusal = json!({ "code": 50007, "message": "Cannot send messages to this user" });
+        return Ok(Response::builder().status(403).body(Body::from(refusal.to_string())).unwrap());
+      }
+      message_json(id, channel, BOT, None, &body)
+    }
+    ("PATCH", ["channels", channel, "messages", message]) => {
+      message_json(message.parse().unwrap(), channel.parse().unwrap(), BOT, None, &body)
+    }
+    _ => return Ok(Response::builder().status(204).body(Body::empty()).unwrap()),
+  };
+
+  Ok(Response::builder()
+    .header("content-type", "application/json")
+    .body(Body::from(reply.to_string()))
+    .unwrap())
+}
+
+fn message_json(id: u64, channel: u64, author: u64, guild: Option<u64>, body: &Value) -> Value {
+  let mut message = json!({
+    "id": id.to_string(),
+    "channel_id": channel.to_string(),
+    "author": { "id": author.to_string(), "username": format!("user{}", author), "discriminator": "0001", "avatar": null },
+    "content": body["content"].as_str().unwrap_or(""),
+    "timestamp": "2023-01-01T00:00:00.000000+00:00",
+    "edited_timestamp": null,
+    "tts": false,
+    "mention_everyone": false,
+    "mentions": [],
+    "mention_roles": [],
+    "attachments": [],
+    "embeds": [],
+    "pinned": false,
+    "type": 0,
+  });
+  if let Some(guild) = guild {
+    message["guild_id"] = json!(guild.to_string());
+  }
+  message
+}
+
+// Where each user's DMs with the bot are.
+fn dm_channel(user: u64) -> u64 {
+  500 + user
+}
+
+// The path twilight uses to add `emoji` to a message.
+fn reaction_path(channel: u64, message: u64, emoji: &str) -> String {
+  let encoded: String = emoji.bytes().map(|byte| format!("%{:02X}", byte)).collect();
+  format!("/channels/{}/messages/{}/reactions/{}/@me", channel, message, encoded)
+}
+
+struct Harness {
+  discord: FakeDiscord,
+  bot: DiscordBot,
+  bot_events: UnboundedReceiver<BotEvent>,
+  next_message: u64,
+}
+
+impl Harness {
+  fn new() -> Self {
+    let discord = FakeDiscord::start();
+    let words = ["cat", "car", "cart", "care", "dog", "door", "tree", "trees", "apple", "banana", "scatter", "cathedral"];
+    let dictionary = Arc::new(Dictionary::from_words(words.map(String::from)));
+    let (sender, bot_events) = mpsc::unbounded_channel();
+    let bot = DiscordBot::new(String::from("w::"), discord.client(), dictionary, LobbySettings::default(), 3, sender);
+
+    Self { discord, bot, bot_events, next_message: 1 }
+  }
+
+  /// A harness whose games use `criteria` every round.
+  fn with_criteria(criteria: Vec<Criteron>) -> Self {
+    let mut harness = Self::new();
+    harness.bot = harness.bot.with_criteria(criteria);
+    harness
+  }
+
+  /// Sends the bot a message in the game's channel, returning its ID.
+  async fn say(&mut self, author: u64, content: &str) -> u64 {
+    self.send(author, CHANNEL, Some(GUILD), content).await
+  }
+
+  /// DMs the bot, returning the message's ID.
+  async fn dm(&mut self, author: u64, content: &str) -> u64 {
+    self.send(author, dm_channel(author), None, content).await
+  }
+
+  async fn send(&mut self, author: u64, channel: u64, guild: Option<u64>, content: &str) -> u64 {
+    let id = self.next_message;
+    self.next_message += 1;
+    let message: Message = serde_json::from_value(message_json(id, channel, author, guild, &json!({ "content": content }))).unwrap();
+    self.bot.handle_message(message).await;
+    id
+  }
+
+  /// Waits for the bot to say something in the game's channel that `predicate` likes.
+  async fn wait_to_say(&self, what: &str, predicate: impl Fn(&str) -> bool) -> Request {
+    let path = format!("/channels/{}/messages", CHANNEL);
+    self.discord.wait_for(what, |request| request.method == "POST" && request.path == path && predicate(request.content())).await
+  }
+
+  async fn wait_for_reaction(&self, channel: u64, message: u64, emoji: &str) {
+    let path = reaction_path(channel, message, emoji);
+    self.discord.wait_for(&format!("{} on message {}", emoji, message), |request| {
+      request.method == "PUT" && request.path == path
+    }).await;
+  }
+
+  /// DMs the bot a word and waits for it to react with `emoji`.
+  async fn play(&mut self, author: u64, word: &str, emoji: &str) {
+    let id = self.dm(author, word).await;
+    self.wait_for_reaction(dm_channel(author), id, emoji).await;
+  }
+
+  fn reacted(&self, channel: u64, message: u64, emoji: &str) -> bool {
+    let path = reaction_path(channel, message, emoji);
+    self.discord.requests().iter().any(|request| request.method == "PUT" && request.path == path)
+  }
+}
+
+#[tokio::test]
+async fn plays_a_whole_game_by_dm() {
+  // Every word starting with "ca" is worth a point, and only to whoever sends it first.
+  let mut harness = Harness::with_criteria(vec![Criteron::starting_with("ca")]);
+  let new = harness.say(ALICE, "w::new rounds=1 time=10 block=1 long=off rare=off unique=off hidden=0").await;
+  let header = harness.wait_to_say("the lobby header", |content| content.contains(&format!("Host: <@!{}>", ALICE))).await;
+  assert!(!header.body["components"].as_array().unwrap().is_empty(), "the header should have lobby buttons");
+  harness.wait_for_reaction(CHANNEL, new, "✅").await;
+
+  let join = harness.say(BOB, "w::join").await;
+  harness.wait_for_reaction(CHANNEL, join, "✅").await;
+  harness.discord.wait_for("the header to show Bob", |request| {
+    request.method == "PATCH" && request.content().contains(&format!("<@!{}>", BOB))
+  }).await;
+
+  let start = harness.say(ALICE, "w::start").await;
+  harness.wait_for_reaction(CHANNEL, start, "✅").await;
+  harness.discord.wait_for("the starting message", |request| request.content().contains("Starting now")).await;
+  harness.discord.wait_for("the lobby buttons to go", |request| {
+    request.method == "PATCH" && request.body["components"].as_array().is_some_and(Vec::is_empty)
+  }).await;
+
+  harness.discord.wait_for("the round to be announced", |request| {
+    request.content().contains("Round 1 of 1") && request.content().contains("Start with `ca`")
+  }).await;
+  let dms = harness.discord.requests().into_iter().filter(|request| request.path == "/users/@me/channels").count();
+  assert!(dms >= 2, "both players should have been DMed");
+
+  harness.play(ALICE, "notaword", "❌").await;
+  harness.play(ALICE, "cat", "✅").await;
+  harness.play(ALICE, "Cat ", "🔁").await;
+  harness.play(BOB, "cat", "🛑").await;
+  harness.discord.wait_for("Bob to be told why", |request| {
+    request.path == format!("/channels/{}/messages", dm_channel(BOB)) && request.content().contains("`cat` is blocked")
+  }).await;
+  harness.play(BOB, "cart", "✅").await;
+  harness.play(BOB, "care", "✅").await;
+
+  let results = harness.discord.wait_for("the round's results", |request| request.embed_title() == "Round 1 of 1 results").await;
+  let fields = &results.body["embeds"][0]["fields"];
+  assert_eq!(fields[0]["name"], "#1 · 2 points (+2 this round)");
+  assert_eq!(fields[0]["value"], format!("<@{}>\n`cart`, `care`", BOB));
+  assert_eq!(fields[1]["name"], "#2 · 1 points (+1 this round)");
+  assert_eq!(fields[1]["value"], format!("<@{}>\n`cat`", ALICE));
+
+  let standings = harness.discord.wait_for("the final results", |request| {
+    request.embed_title() == "Words Against Friends: Final results"
+  }).await;
+  let standings = standings.body["embeds"][0]["description"].as_str().unwrap().to_string();
+  assert!(standings.starts_with(&format!(":trophy: <@{}> wins with **2** points!", BOB)), "{}", standings);
+  assert!(standings.contains(&format!("**#2** <@{}>: 1 points", ALICE)), "{}", standings);
+  harness.discord.wait_for("the header to close", |request| {
+    request.method == "PATCH" && request.content().contains("This game is over!")
+  }).await;
+
+  // Once the game reports back, the channel is free for another one.
+  let finished = harness.bot_events.recv().await.unwrap();
+  harness.bot.handle_bot_event(finished).await;
+  let again = harness.say(BOB, "w::new").await;
+  harness.wait_for_reaction(CHANNEL, again, "✅").await;
+}
+
+#[tokio::test]
+async fn explains_commands_that_cannot_run() {
+  let mut harness = Harness::new();
+
+  harness.say(ALICE, "w::start").await;
+  harness.wait_to_say("no game", |content| content == "There is no game in this channel yet!").await;
+
+  harness.say(ALICE, "w::new").await;
+  harness.say(ALICE, "w::join").await;
+  harness.wait_to_say("one game only", |content| content == "You may only be in one game at a time!").await;
+
+  harness.say(BOB, "w::join").await;
+  harness.say(BOB, "w::start").await;
+  harness.wait_to_say("host only", |content| content == "Only the host may start the game!").await;
+
+  // The fake Discord can't say who the moderators are, so only the host may cancel.
+  harness.say(BOB, "w::cancel").await;
+  let checked = harness.bot_events.recv().await.unwrap();
+  harness.bot.handle_bot_event(checked).await;
+  harness.wait_to_say("host or moderator only", |content| content == "Only the host or a moderator may cancel the game!").await;
+
+  harness.say(ALICE, "w::set rounds=50").await;
+  harness.wait_to_say("a bad setting", |content| content == "`rounds` must be a number from 1 to 10.").await;
+
+  harness.say(ALICE, "w::dance").await;
+  harness.wait_to_say("an unknown command", |content| content.starts_with("There's no `w::dance` command")).await;
+}
+
+#[tokio::test]
+async fn plays_in_the_channel_and_hides_guesses() {
+  let mut harness = Harness::new();
+  harness.discord.state.closed_dms.lock().unwrap().push(BOB);
+
+  harness.say(ALICE, "w::new rounds=1 time=10 play=channel").await;
+  harness.say(BOB, "w::join").await;
+  harness.say(ALICE, "w::start").await;
+  harness.discord.wait_for("the round to be announced", |request| {
+    request.path == format!("/channels/{}/messages", CHANNEL) && request.content().contains("Round 1 of 1")
+  }).await;
+
+  // Chat with spaces in it isn't a guess.
+  let chat = harness.say(ALICE, "good luck").await;
+  let word = harness.say(ALICE, "notaword").await;
+  harness.discord.wait_for("the guess to be taken down", |request| {
+    request.method == "DELETE" && request.path == format!("/channels/{}/messages/{}", CHANNEL, word)
+  }).await;
+  // Deleting the guess would take a reaction with it, so the verdict comes by DM.
+  harness.discord.wait_for("Alice to be told how her guess did", |request| {
+    request.path == format!("/channels/{}/messages", dm_channel(ALICE)) && request.content() == "❌ `notaword`"
+  }).await;
+  assert!(!harness.reacted(CHANNEL, word, "❌"));
+  assert!(!harness.discord.requests().iter().any(|request| request.path.contains(&format!("/messages/{}", chat))));
+  assert!(!harness.discord.requests().iter().any(|request| request.content().contains("Round 1 of 1") && request.path != format!("/channels/{}/messages", CHANNEL)));
+
+  // Without DMs, the verdict is given in the open, but not the word.
+  let word = harness.say(BOB, "banana").await;
+  harness.discord.wait_for("Bob's guess to be taken down", |request| {
+    request.method == "DELETE" && request.path == format!("/channels/{}/messages/{}", CHANNEL, word)
+  }).await;
+  harness.wait_to_say("Bob to be told in the channel", |content| content == format!("<@!{}> ❌", BOB)).await;
+  assert!(!harness.discord.requests().iter().any(|request| {
+    request.path == format!("/channels/{}/messages", CHANNEL) && request.content().contains("banana")
+  }));
+}
+
+#[tokio::test]
+async fn moves_players_whose_dms_are_closed_to_the_channel() {
+  let mut harness = Harness::new();
+  harness.discord.state.closed_dms.lock().unwrap().push(BOB);
+
+  harness.say(ALICE, "w::new rounds=1 time=10").await;
+  harness.say(BOB, "w::join").await;
+  harness.say(ALICE, "w::start").await;
+  harness.discord.wait_for("Bob to be told to play here", |request| {
+    request.path == format!("/channels/{}/messages", CHANNEL)
+      && request.content().contains(&format!("<@!{}> I couldn't DM you", BOB))
+  }).await;
+  harness.discord.wait_for("the round to be announced in the channel", |request| {
+    request.path == format!("/channels/{}/messages", CHANNEL) && request.content().contains("Round 1 of 1")
+  }).await;
+
+  // Guesses in the channel are hidden, and Bob can't be told how his went by DM.
+  harness.say(BOB, "notaword").await;
+  harness.wait_to_say("Bob's word to be scored", |content| content == format!("<@!{}> ❌", BOB)).await;
+}
+
+#[tokio::test]
+async fn retries_when_discord_falters() {
+  let mut harness = Harness::new();
+  harness.discord.state.outage.store(2, Ordering::SeqCst);
+
+  // One request at a time, so it's the one that sees the outage.
+  harness.say(ALICE, "w::start").await;
+  harness.discord.wait_until("the reply to be sent twice more after failing", |requests| {
+    requests.iter().filter(|request| request.content() == "There is no game in this channel yet!").count() == 3
+  }).await;
+
+  let new = harness.say(ALICE, "w::new").await;
+  harness.wait_for_reaction(CHANNEL, new, "✅").await;
+}